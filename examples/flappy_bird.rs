@@ -2,10 +2,11 @@ mod helper_functions;
 
 use neat::population;
 use macroquad::prelude::*;
+use neat::config::NeatConfig;
 use neat::genome::Genome;
 use helper_functions::flappy::*;
 
-async fn eval_genomes(genomes: &mut Vec<Genome>, display: bool) {
+async fn eval_genomes(genomes: &mut Vec<Genome>, config: &NeatConfig, display: bool) {
     let mut game = Game::new(genomes.len());
     genomes.sort();
     loop {
@@ -16,7 +17,7 @@ async fn eval_genomes(genomes: &mut Vec<Genome>, display: bool) {
         for i in 0..genomes.len() {
             let (flappy, pipe1, pipe2, x_dist, velocity) = game.individual_inputs(i);
             let inputs = vec![flappy, pipe1, pipe2, x_dist, velocity];
-            let output = genomes[i].feed_forward(inputs.clone());
+            let output = genomes[i].feed_forward(inputs.clone(), config);
             if output[0] > 0.55 {
                 game.flappy[i].jump();
             }
@@ -40,17 +41,17 @@ async fn eval_genomes(genomes: &mut Vec<Genome>, display: bool) {
 #[macroquad::main("BasicShapes")]
 async fn main() {
 
-    let mut population = population::Population::new(350, 5, 1, 0);
+    let mut population = population::Population::new(350, 5, 1, 0).expect("valid population parameters");
 
     for _ in 0..40 {
-        eval_genomes(&mut population.genomes, false).await;
+        eval_genomes(&mut population.genomes, &population.config, false).await;
         population.evolve();
         println!("{}", population.get_info());
     }
 
     if let Some(ref champion) = population.champion {
         loop {
-            eval_genomes(vec![champion.clone()].as_mut(), true).await;
+            eval_genomes(vec![champion.clone()].as_mut(), &population.config, true).await;
         }
     }
     println!("{}", population.get_info());
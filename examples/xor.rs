@@ -1,7 +1,8 @@
+use neat::config::NeatConfig;
 use neat::genome::Genome;
 use neat::population;
 
-fn eval_genomes(genome: &mut Genome, display: bool) {
+fn eval_genomes(genome: &mut Genome, config: &NeatConfig, display: bool) {
     let xor = vec![
         (vec![1.0, 0.0], vec![1.0]),
         (vec![1.0, 1.0], vec![0.0]),
@@ -11,7 +12,7 @@ fn eval_genomes(genome: &mut Genome, display: bool) {
 
     let mut fitness = 0.0;
     for (xi, xo) in &xor {
-        let output = genome.feed_forward(xi.clone());
+        let output = genome.feed_forward(xi.clone(), config);
         fitness += (xo[0] - output[0]).powi(2);
         if display {
             println!("input: {:?}", xi);
@@ -24,17 +25,25 @@ fn eval_genomes(genome: &mut Genome, display: bool) {
 
 fn main() {
 
+    let config = NeatConfig {
+        fitness_threshold: Some(3.9),
+        ..NeatConfig::default()
+    };
     let mut best_champion: Option<Genome> = None;
     for _ in 0..45 {
-        let mut population = population::Population::new(50, 2, 1, 0);
+        let mut population =
+            population::Population::with_config(50, 2, 1, 0, config.clone()).expect("valid population parameters");
         for _ in 0..45 {
-            population.evaluate(&eval_genomes);
+            population.evaluate(&|genome, display| eval_genomes(genome, &config, display));
+            if population.is_solved() {
+                break;
+            }
         }
         if let Some(ref champion) = population.champion {
             if best_champion.is_none() || champion.fitness > best_champion.as_ref().unwrap().fitness {
                 best_champion = Some(champion.clone());
             }
-            eval_genomes(&mut champion.clone(), false);
+            eval_genomes(&mut champion.clone(), &config, false);
         }
         println!("{}", population.get_info());
     }
@@ -42,6 +51,6 @@ fn main() {
     if best_champion.is_some() {
         let champ = best_champion.as_ref().unwrap();
         println!("Best Champion: {}", champ);
-        eval_genomes(&mut champ.clone(), true);
+        eval_genomes(&mut champ.clone(), &config, true);
     }
 }
@@ -0,0 +1,332 @@
+use crate::genes::ActivationFunction;
+use rand::{Rng, RngCore};
+
+// How genomes are assigned to species
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpeciationMode {
+    // Join the first species whose representative is within the compatibility threshold
+    FirstMatch,
+    // Join the species whose representative is closest, if within the compatibility threshold
+    NearestRepresentative,
+}
+
+// How matching connection genes are combined during crossover
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CrossoverMode {
+    // Inherit the gene from either parent at random
+    RandomParent,
+    // Inherit the fitter parent's gene with the mean of both parents' weights
+    AverageWeights,
+}
+
+// How the structural mutation probabilities change over a run, as a scale on the configured ones
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MutationSchedule {
+    Constant,
+    // Scale moves linearly from 1 to `end_scale` over `generations`, then stays there
+    Linear { generations: usize, end_scale: f64 },
+    // Scale is multiplied by `decay` every generation, down to `min_scale`
+    Exponential { decay: f64, min_scale: f64 },
+}
+
+impl MutationSchedule {
+    pub fn scale(&self, generation: usize) -> f64 {
+        match *self {
+            MutationSchedule::Constant => 1.0,
+            MutationSchedule::Linear { generations, end_scale } => {
+                let progress = if generations == 0 {
+                    1.0
+                } else {
+                    (generation as f64 / generations as f64).min(1.0)
+                };
+                1.0 + (end_scale - 1.0) * progress
+            }
+            MutationSchedule::Exponential { decay, min_scale } => decay.powf(generation as f64).max(min_scale),
+        }
+    }
+}
+
+// Distribution weights of new connections are drawn from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WeightInit {
+    // Uniform within -range..range
+    Uniform(f64),
+    Normal { mean: f64, std: f64 },
+}
+
+impl WeightInit {
+    pub fn sample(&self, rng: &mut dyn RngCore) -> f64 {
+        match *self {
+            WeightInit::Uniform(range) => rng.gen_range(-range..range),
+            WeightInit::Normal { mean, std } => {
+                // Box-Muller transform
+                let u1: f64 = 1.0 - rng.gen::<f64>();
+                let u2: f64 = rng.gen();
+                mean + std * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+            }
+        }
+    }
+}
+
+// Parameters controlling evolution, defaults match the values the library was tuned with
+#[derive(Clone, Debug, PartialEq)]
+pub struct NeatConfig {
+    // Fraction of each species (fittest first) kept as parents when culling
+    pub survival_threshold: f64,
+
+    // Multiplier on the fitness of species younger than `young_age_threshold` generations when
+    // allocating offspring, giving new species time to optimize
+    pub young_species_bonus: f64,
+    pub young_age_threshold: usize,
+
+    // Largest share of the population a single species can claim as offspring, the excess goes to
+    // the other species
+    pub max_species_offspring_fraction: f64,
+
+    // Number of the fittest genomes copied unchanged into the next generation. With 0 only the
+    // generation's best genome is carried over, in addition to the full population
+    pub global_elitism: usize,
+
+    // Keep the species with the fittest genome, or the `protected_species_count` fittest species,
+    // from being removed for stagnating
+    pub species_elitism: bool,
+    pub protected_species_count: usize,
+
+    // Generations without a new champion after which only the two best species are kept
+    pub global_stagnation_limit: usize,
+
+    // Fitness at which the task counts as solved
+    pub fitness_threshold: Option<f64>,
+
+    // Genomes closer than this to a species' representative can join it
+    pub compatibility_threshold: f64,
+    // Weight of the fraction of shared nodes with different activation functions in the distance
+    pub compatibility_activation_coefficient: f64,
+    // Leave connections disabled in either genome out of the compatibility distance
+    pub compatibility_ignore_disabled: bool,
+    // Disjoint and excess counts are divided by the size of the larger genome only when it has at
+    // least this many connections, the NEAT paper uses 20 so small genomes aren't over-normalized
+    pub compatibility_normalization_threshold: usize,
+    pub speciation_mode: SpeciationMode,
+    // Compare genomes that have a `behavior` by the euclidean distance between their behaviors,
+    // against the same threshold, instead of by their structure
+    pub use_behavioral_speciation: bool,
+    // Genomes compared when measuring `Population::diversity`
+    pub diversity_sample_size: usize,
+    // Use each species' fittest genome as its representative instead of a random member
+    pub deterministic_representative: bool,
+
+    pub crossover_mode: CrossoverMode,
+    // Treat the genome with fewer connections as the fitter parent when fitness is equal
+    pub crossover_equal_fitness_prefer_smaller: bool,
+    // Nodes both parents have take their activation and parameter from either parent at random,
+    // instead of always from the fitter one
+    pub crossover_node_parameters: bool,
+    // Parents per crossover, more than 2 breeds with `Genome::multi_crossover`
+    pub crossover_parents: usize,
+
+    // Chance per mutation of perturbing the weights, adding a node and adding a connection
+    pub weight_mutation_prob: f64,
+    pub new_node_prob: f64,
+    pub new_connection_prob: f64,
+    // Apply `weight_mutation_prob` to each connection on its own rather than to all of a
+    // genome's weights at once
+    pub per_connection_weight_mutation: bool,
+    // Weights of the initial connections and of connections added by mutation
+    pub weight_init: WeightInit,
+    // Applied to `new_node_prob` and `new_connection_prob` by the population's age, e.g. to explore
+    // early and refine later
+    pub mutation_schedule: MutationSchedule,
+    // Random node pairs tried when adding a connection to a genome with more nodes than
+    // `exhaustive_connection_max_nodes`, smaller genomes consider every possible connection
+    pub connection_mutation_attempts: usize,
+    pub exhaustive_connection_max_nodes: usize,
+
+    // Activation of output nodes, or one per output index when `output_activation_functions` is set
+    pub output_activation_function: ActivationFunction,
+    pub output_activation_functions: Option<Vec<ActivationFunction>>,
+    // Range network outputs are clamped to, for tasks that expect bounded outputs
+    pub output_clamp: Option<(f64, f64)>,
+    // Activation given to hidden nodes when they are added
+    pub default_activation_function: ActivationFunction,
+    // Chance per mutation of switching a hidden node to another of `activation_functions`
+    pub activation_mutation_prob: f64,
+    pub activation_functions: Vec<ActivationFunction>,
+    // Functions referred to by `ActivationFunction::Custom`, added with `register_activation`
+    pub custom_activation_functions: Vec<fn(f64) -> f64>,
+    // Negative slope of new LeakyReLU nodes and starting slope of new PReLU nodes
+    pub leaky_relu_slope: f64,
+    // Bounds PReLU slopes are kept in while mutating
+    pub prelu_slope_range: (f64, f64),
+
+    // Whether the bias node gets connections, and whether only hidden nodes do. The bias node is
+    // kept either way so node ids stay the same
+    pub use_bias: bool,
+    pub bias_to_hidden_only: bool,
+    // Output indices, as in `output_activation_functions`, that never get a bias connection
+    pub no_bias_outputs: Vec<usize>,
+
+    // Selection fitness lost per layer spanned by each enabled connection, rewarding locally wired
+    // networks. The fitness genomes report is left as assigned
+    pub wiring_cost_coefficient: f64,
+
+    // Offspring connections with weights closer to zero than this are disabled
+    pub weight_prune_threshold: Option<f64>,
+
+    // Structural mutations stop growing a genome once it reaches these sizes
+    pub max_nodes: Option<usize>,
+    pub max_connections: Option<usize>,
+    // Mutation adds nodes to genomes with fewer hidden nodes than this
+    pub min_hidden_nodes: usize,
+
+    // Reuse the fitness of genomes whose structure and weights were already evaluated in the
+    // previous generation, e.g. elites, instead of calling the fitness function again. Only for
+    // deterministic fitness functions
+    pub cache_fitness: bool,
+    // Fitness given to genomes whose fitness function returned NaN or infinity
+    pub invalid_fitness_value: f64,
+
+    // Seed for the population's random number generator, runs with the same seed and fitness
+    // function evolve identically
+    pub seed: Option<u64>,
+}
+
+impl Default for NeatConfig {
+    fn default() -> Self {
+        Self {
+            survival_threshold: 0.5,
+            young_species_bonus: 1.0,
+            young_age_threshold: 10,
+            max_species_offspring_fraction: 0.5,
+            global_elitism: 0,
+            species_elitism: false,
+            protected_species_count: 0,
+            global_stagnation_limit: 20,
+            fitness_threshold: None,
+            compatibility_threshold: 2.0,
+            compatibility_activation_coefficient: 0.0,
+            compatibility_ignore_disabled: false,
+            compatibility_normalization_threshold: 0,
+            speciation_mode: SpeciationMode::FirstMatch,
+            use_behavioral_speciation: false,
+            diversity_sample_size: 50,
+            deterministic_representative: false,
+            crossover_mode: CrossoverMode::RandomParent,
+            crossover_equal_fitness_prefer_smaller: false,
+            crossover_node_parameters: false,
+            crossover_parents: 2,
+            weight_mutation_prob: 0.7,
+            new_node_prob: 0.2,
+            new_connection_prob: 0.5,
+            per_connection_weight_mutation: false,
+            mutation_schedule: MutationSchedule::Constant,
+            weight_init: WeightInit::Uniform(5.0),
+            connection_mutation_attempts: 20,
+            exhaustive_connection_max_nodes: 16,
+            output_activation_function: ActivationFunction::SteepenedSigmoid,
+            output_activation_functions: None,
+            output_clamp: None,
+            default_activation_function: ActivationFunction::SteepenedSigmoid,
+            activation_mutation_prob: 0.0,
+            activation_functions: vec![
+                ActivationFunction::Sigmoid,
+                ActivationFunction::SteepenedSigmoid,
+                ActivationFunction::Tanh,
+                ActivationFunction::ReLU,
+                ActivationFunction::LeakyReLU,
+                ActivationFunction::PReLU,
+            ],
+            custom_activation_functions: vec![],
+            leaky_relu_slope: 0.01,
+            prelu_slope_range: (0.0, 1.0),
+            use_bias: true,
+            bias_to_hidden_only: false,
+            no_bias_outputs: vec![],
+            wiring_cost_coefficient: 0.0,
+            weight_prune_threshold: None,
+            max_nodes: None,
+            max_connections: None,
+            min_hidden_nodes: 0,
+            cache_fitness: false,
+            invalid_fitness_value: f64::MIN,
+            seed: None,
+        }
+    }
+}
+
+impl NeatConfig {
+    // Adds a function hidden nodes can mutate to, returning the activation that refers to it
+    pub fn register_activation(&mut self, function: fn(f64) -> f64) -> ActivationFunction {
+        let activation = ActivationFunction::Custom(self.custom_activation_functions.len());
+        self.custom_activation_functions.push(function);
+        self.activation_functions.push(activation);
+        activation
+    }
+
+    // Checks that the parameters are usable, describing the first problem found
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.survival_threshold) {
+            return Err(format!("survival_threshold must be within 0..=1, got {}", self.survival_threshold));
+        }
+        if !(self.max_species_offspring_fraction > 0.0 && self.max_species_offspring_fraction <= 1.0) {
+            return Err(format!(
+                "max_species_offspring_fraction must be within (0, 1], got {}",
+                self.max_species_offspring_fraction
+            ));
+        }
+        if self.compatibility_threshold <= 0.0 {
+            return Err(format!(
+                "compatibility_threshold must be positive, got {}",
+                self.compatibility_threshold
+            ));
+        }
+        if self.wiring_cost_coefficient < 0.0 {
+            return Err(format!(
+                "wiring_cost_coefficient must not be negative, got {}",
+                self.wiring_cost_coefficient
+            ));
+        }
+        let valid_schedule = match self.mutation_schedule {
+            MutationSchedule::Constant => true,
+            MutationSchedule::Linear { end_scale, .. } => end_scale >= 0.0,
+            MutationSchedule::Exponential { decay, min_scale } => decay > 0.0 && min_scale >= 0.0,
+        };
+        if !valid_schedule {
+            return Err(format!("mutation_schedule must not scale below 0: {:?}", self.mutation_schedule));
+        }
+        let valid_weight_init = match self.weight_init {
+            WeightInit::Uniform(range) => range > 0.0,
+            WeightInit::Normal { std, .. } => std >= 0.0,
+        };
+        if !valid_weight_init {
+            return Err(format!("weight_init has an empty range: {:?}", self.weight_init));
+        }
+        if self.crossover_parents < 2 {
+            return Err(format!("crossover_parents must be at least 2, got {}", self.crossover_parents));
+        }
+        if self.young_species_bonus < 0.0 {
+            return Err(format!("young_species_bonus must not be negative, got {}", self.young_species_bonus));
+        }
+        if self.activation_mutation_prob > 0.0 && self.activation_functions.is_empty() {
+            return Err("activation_functions must not be empty when activations mutate".to_string());
+        }
+        let fixed = [self.default_activation_function, self.output_activation_function];
+        let outputs = self.output_activation_functions.iter().flatten();
+        for activation in self.activation_functions.iter().chain(&fixed).chain(outputs) {
+            if let ActivationFunction::Custom(index) = activation {
+                if *index >= self.custom_activation_functions.len() {
+                    return Err(format!("custom activation function {} is not registered", index));
+                }
+            }
+        }
+        if let Some((low, high)) = self.output_clamp {
+            if low > high {
+                return Err(format!("output_clamp is empty: {:?}", self.output_clamp));
+            }
+        }
+        if self.prelu_slope_range.0 > self.prelu_slope_range.1 {
+            return Err(format!("prelu_slope_range is empty: {:?}", self.prelu_slope_range));
+        }
+        Ok(())
+    }
+}
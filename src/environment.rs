@@ -0,0 +1,80 @@
+use rand::{Rng, RngCore};
+
+// Sequential task a network acts in, such as a control or reinforcement learning problem
+pub trait Environment {
+    // Starts a new episode, returning the first observation
+    fn reset(&mut self) -> Vec<f64>;
+
+    // Applies the network's outputs, returning the next observation, the reward and whether the
+    // episode is over
+    fn step(&mut self, action: &[f64]) -> (Vec<f64>, f64, bool);
+
+    // Length of each observation
+    fn input_size(&self) -> usize;
+
+    // Bounds of the values `sample_inputs` draws from
+    fn input_range(&self) -> (f64, f64) {
+        (-1.0, 1.0)
+    }
+
+    // Random observation with every value uniform in `input_range`, for fuzzing networks
+    fn sample_inputs(&self, rng: &mut dyn RngCore) -> Vec<f64> {
+        let (low, high) = self.input_range();
+        (0..self.input_size()).map(|_| rng.gen_range(low..=high)).collect()
+    }
+}
+
+// Total reward `network` collects over `episodes` episodes, feeding observations to it and its
+// outputs back as actions
+pub fn run_episodes(environment: &mut dyn Environment, network: &dyn Fn(&[f64]) -> Vec<f64>, episodes: usize) -> f64 {
+    let mut total_reward = 0.0;
+    for _ in 0..episodes {
+        let mut observation = environment.reset();
+        loop {
+            let (next_observation, reward, done) = environment.step(&network(&observation));
+            total_reward += reward;
+            if done {
+                break;
+            }
+            observation = next_observation;
+        }
+    }
+    total_reward
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    struct Sensor;
+
+    impl Environment for Sensor {
+        fn reset(&mut self) -> Vec<f64> {
+            vec![0.0; 3]
+        }
+
+        fn step(&mut self, _action: &[f64]) -> (Vec<f64>, f64, bool) {
+            (vec![0.0; 3], 0.0, true)
+        }
+
+        fn input_size(&self) -> usize {
+            3
+        }
+
+        fn input_range(&self) -> (f64, f64) {
+            (2.0, 5.0)
+        }
+    }
+
+    #[test]
+    fn sample_inputs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let inputs = Sensor.sample_inputs(&mut rng);
+            assert_eq!(inputs.len(), 3);
+            assert!(inputs.iter().all(|input| (2.0..=5.0).contains(input)));
+        }
+    }
+}
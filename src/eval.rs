@@ -0,0 +1,84 @@
+// Helpers for fitness functions on supervised datasets of (inputs, expected outputs) pairs. The
+// network is anything mapping inputs to outputs, such as a compiled genome
+
+// Mean squared error over every output of every case. Panics if a network output doesn't have
+// as many values as its expected output
+pub fn mse(network: &dyn Fn(&[f64]) -> Vec<f64>, dataset: &[(Vec<f64>, Vec<f64>)]) -> f64 {
+    let mut total = 0.0;
+    let mut count = 0;
+    for (inputs, expected) in dataset {
+        let outputs = network(inputs);
+        assert_eq!(outputs.len(), expected.len(), "network outputs and expected outputs differ in length");
+        for (output, expected) in outputs.iter().zip(expected) {
+            total += (output - expected).powi(2);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        0.0
+    } else {
+        total / count as f64
+    }
+}
+
+// Fraction of cases where every output is on the same side of `threshold` as its expected value
+pub fn accuracy(network: &dyn Fn(&[f64]) -> Vec<f64>, dataset: &[(Vec<f64>, Vec<f64>)], threshold: f64) -> f64 {
+    if dataset.is_empty() {
+        return 0.0;
+    }
+    let correct = dataset
+        .iter()
+        .filter(|(inputs, expected)| {
+            let outputs = network(inputs);
+            assert_eq!(outputs.len(), expected.len(), "network outputs and expected outputs differ in length");
+            outputs
+                .iter()
+                .zip(expected)
+                .all(|(output, expected)| (*output > threshold) == (*expected > threshold))
+        })
+        .count();
+    correct as f64 / dataset.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NeatConfig;
+    use crate::genes::ActivationFunction;
+    use crate::genome::Genome;
+    use crate::innovation_record::InnovationRecord;
+
+    fn xor() -> Vec<(Vec<f64>, Vec<f64>)> {
+        vec![
+            (vec![0.0, 0.0], vec![0.0]),
+            (vec![0.0, 1.0], vec![1.0]),
+            (vec![1.0, 0.0], vec![1.0]),
+            (vec![1.0, 1.0], vec![0.0]),
+        ]
+    }
+
+    #[test]
+    fn xor_error() {
+        // Only the bias reaches the output, which always gives 0.75
+        let mut innovation_record = InnovationRecord::new();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        genome.set_weight(0, 0.0);
+        genome.set_weight(1, 0.0);
+        genome.set_weight(2, 0.75);
+        genome.node[3].activation = ActivationFunction::None;
+        let network = genome.compile(&NeatConfig::default());
+
+        assert_eq!(mse(&network, &xor()), (0.75 * 0.75 * 2.0 + 0.25 * 0.25 * 2.0) / 4.0);
+        assert_eq!(accuracy(&network, &xor(), 0.5), 0.5);
+
+        let exact = |inputs: &[f64]| vec![if inputs[0] != inputs[1] { 1.0 } else { 0.0 }];
+        assert_eq!(mse(&exact, &xor()), 0.0);
+        assert_eq!(accuracy(&exact, &xor(), 0.5), 1.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn output_length_mismatch() {
+        mse(&|_: &[f64]| vec![0.0, 0.0], &xor());
+    }
+}
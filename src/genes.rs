@@ -1,4 +1,5 @@
-use rand::Rng;
+use crate::config::WeightInit;
+use rand::{Rng, RngCore};
 
 #[derive(Clone, Debug, Copy)]
 pub struct ConnectionGene {
@@ -8,6 +9,8 @@ pub struct ConnectionGene {
     pub weight: f64,
     pub enabled: bool,
     pub is_recurrent: bool,
+    // Frozen connections keep their weight and enabled state through mutation
+    pub frozen: bool,
 }
 
 impl ConnectionGene {
@@ -19,14 +22,14 @@ impl ConnectionGene {
             enabled: true,
             innovation,
             is_recurrent: false,
+            frozen: false,
         }
     }
 
-    pub fn mutate_weight(&mut self) {
-        let mut rng = rand::thread_rng();
-
+    // Perturbs the weight, or replaces it with one drawn from `weight_init`
+    pub fn mutate_weight(&mut self, weight_init: &WeightInit, rng: &mut dyn RngCore) {
         if rng.gen::<f64>() < 0.1 {
-            self.weight = rng.gen_range(-5.0..5.0);
+            self.weight = weight_init.sample(rng);
         } else {
             // add/subtract 20%
             self.weight += self.weight * rng.gen_range(-0.2..0.2);
@@ -41,6 +44,9 @@ pub struct NodeGene {
     pub node_layer: usize,
     pub sum_inputs: f64,
     pub sum_outputs: f64,
+    pub activation: ActivationFunction,
+    // Slope for negative inputs of LeakyReLU and PReLU nodes
+    pub param: f64,
 }
 
 impl NodeGene {
@@ -57,6 +63,8 @@ impl NodeGene {
             node_layer,
             sum_inputs,
             sum_outputs,
+            activation: ActivationFunction::SteepenedSigmoid,
+            param: 0.0,
         }
     }
 }
@@ -69,23 +77,97 @@ pub enum NodeType {
     Hidden,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ActivationFunction {
     None,
     Sigmoid,
+    // Sigmoid with a slope of 4.9 as used in the NEAT paper
+    SteepenedSigmoid,
     Tanh,
     ReLU,
+    // ReLU capped at 6
+    ReLU6,
+    // x * sigmoid(x), a smooth ReLU
+    Swish,
     LeakyReLU,
+    // LeakyReLU whose slope is evolved per node
+    PReLU,
+    // Index of a function registered with `NeatConfig::register_activation`
+    Custom(usize),
 }
 
 impl ActivationFunction {
-    pub fn activate(&self, x: f64) -> f64 {
+    // `custom` holds the functions registered on the config, a custom activation that isn't
+    // registered there passes its input through unchanged
+    pub fn activate(&self, x: f64, custom: &[fn(f64) -> f64]) -> f64 {
         match self {
             ActivationFunction::None => x,
             ActivationFunction::Sigmoid => 1.0 / (1.0 + std::f64::consts::E.powf(-x)),
+            ActivationFunction::SteepenedSigmoid => 1.0 / (1.0 + (-4.9 * x).exp()),
             ActivationFunction::Tanh => x.tanh(),
             ActivationFunction::ReLU => x.max(0.0),
-            ActivationFunction::LeakyReLU => x.max(0.01 * x),
+            ActivationFunction::ReLU6 => x.clamp(0.0, 6.0),
+            ActivationFunction::Swish => x / (1.0 + (-x).exp()),
+            ActivationFunction::LeakyReLU | ActivationFunction::PReLU => x.max(0.01 * x),
+            ActivationFunction::Custom(index) => custom.get(*index).map_or(x, |function| function(x)),
+        }
+    }
+
+    // Activation using the node's parameter as the negative slope of LeakyReLU and PReLU
+    pub fn activate_with_param(&self, x: f64, param: f64, custom: &[fn(f64) -> f64]) -> f64 {
+        match self {
+            ActivationFunction::LeakyReLU | ActivationFunction::PReLU => {
+                if x > 0.0 {
+                    x
+                } else {
+                    param * x
+                }
+            }
+            _ => self.activate(x, custom),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn relu6_and_swish() {
+        let relu6 = |x: f64| ActivationFunction::ReLU6.activate(x, &[]);
+        assert_eq!([relu6(-10.0), relu6(0.0), relu6(3.0), relu6(10.0)], [0.0, 0.0, 3.0, 6.0]);
+
+        let swish = |x: f64| ActivationFunction::Swish.activate(x, &[]);
+        assert!((swish(-10.0) - -10.0 / (1.0 + 10f64.exp())).abs() < 1e-12);
+        assert!(swish(-10.0) < 0.0 && swish(-10.0) > -0.001);
+        assert_eq!(swish(0.0), 0.0);
+        assert!((swish(3.0) - 2.857722).abs() < 1e-6);
+        assert!((swish(10.0) - 9.999546).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mutate_weight_uses_weight_init() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let weight_init = WeightInit::Normal { mean: 0.0, std: 0.1 };
+        let mut replaced = 0;
+        for _ in 0..1000 {
+            let mut gene = ConnectionGene::new(0, 1, 0.0, 0);
+            gene.mutate_weight(&weight_init, &mut rng);
+            assert!(gene.weight.abs() < 1.0);
+            if gene.weight != 0.0 {
+                replaced += 1;
+            }
+        }
+        assert!(replaced > 50);
+    }
+
+    #[test]
+    fn unregistered_custom_activation() {
+        let square: fn(f64) -> f64 = |x| x * x;
+        assert_eq!(ActivationFunction::Custom(0).activate(3.0, &[square]), 9.0);
+        assert_eq!(ActivationFunction::Custom(1).activate(3.0, &[square]), 3.0);
+        assert_eq!(ActivationFunction::Custom(0).activate(3.0, &[]), 3.0);
+    }
+}
@@ -1,8 +1,15 @@
+use crate::config::{CrossoverMode, NeatConfig};
 use crate::genes::{ActivationFunction, ConnectionGene, NodeGene, NodeType};
 use crate::innovation_record::InnovationRecord;
-use rand::Rng;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use std::borrow::Cow;
 use std::cmp::{max, Ordering};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
 
 #[derive(Clone, Debug)]
 pub struct Genome {
@@ -21,10 +28,87 @@ pub struct Genome {
 
     // adj fitness is fitness after fitness sharing
     pub adj_fitness: f64,
+
+    // Optional description of what the network does, set alongside fitness and used for
+    // behavioral speciation. Offspring carry their parent's until they are evaluated
+    pub behavior: Option<Vec<f64>>,
+}
+
+// Connection genes of two genomes classified by innovation number, as used for compatibility
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GenomeDiff {
+    // Innovations both genomes have, with the weight of this genome minus the other's
+    pub matching: Vec<usize>,
+    pub weight_deltas: Vec<f64>,
+    // Innovations only one genome has, within or beyond the other genome's innovation range
+    pub disjoint: Vec<usize>,
+    pub excess: Vec<usize>,
+}
+
+// Terms of `Genome::compatability_distance`: disjoint and excess genes divided by `normalizer`,
+// plus 0.4 times the mean weight difference of matching genes, plus the activation term
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompatibilityBreakdown {
+    pub num_disjoint: usize,
+    pub num_excess: usize,
+    pub num_matching: usize,
+    pub avg_weight_diff: f64,
+    pub normalizer: f64,
+    pub activation_difference: f64,
+    pub total_distance: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GenomeError {
+    // Enabled connections form a loop, so the network can't be evaluated in one pass
+    CycleDetected,
+    // Input, bias or output node missing from the genome
+    MissingNode(usize),
+    // Connection, by innovation number, to or from a node the genome doesn't have
+    DanglingConnection(usize),
+    // Connection, by innovation number, into an input or the bias node
+    ConnectionIntoInput(usize),
+    // More than one connection between the same pair of nodes
+    DuplicateConnection(usize, usize),
+}
+
+impl Display for GenomeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenomeError::CycleDetected => write!(f, "enabled connections form a cycle"),
+            GenomeError::MissingNode(id) => write!(f, "node {} is missing", id),
+            GenomeError::DanglingConnection(innovation) => {
+                write!(f, "connection {} refers to a missing node", innovation)
+            }
+            GenomeError::ConnectionIntoInput(innovation) => {
+                write!(f, "connection {} leads into an input node", innovation)
+            }
+            GenomeError::DuplicateConnection(from, to) => {
+                write!(f, "nodes {} and {} are connected more than once", from, to)
+            }
+        }
+    }
 }
 
+impl Error for GenomeError {}
+
 impl Genome {
+    // Initial weights come from the thread's rng, use `with_config` to pass a seeded one
     pub fn new(inputs: usize, outputs: usize, innovation_record: &mut InnovationRecord) -> Self {
+        Self::with_config(inputs, outputs, innovation_record, &NeatConfig::default(), &mut rand::thread_rng())
+    }
+
+    // Panics if `config.output_activation_functions` doesn't have one function per output
+    pub fn with_config(
+        inputs: usize,
+        outputs: usize,
+        innovation_record: &mut InnovationRecord,
+        config: &NeatConfig,
+        rng: &mut dyn RngCore,
+    ) -> Self {
+        if let Some(functions) = &config.output_activation_functions {
+            assert_eq!(functions.len(), outputs, "one output activation function per output");
+        }
         let mut genome = Self {
             genes: vec![],
             node: vec![],
@@ -34,34 +118,39 @@ impl Genome {
             bias_node: 0,
             fitness: 0.0,
             adj_fitness: 0.0,
+            behavior: None,
         };
 
-        for _ in 0..inputs {
-            genome.node.push(NodeGene::new(
-                innovation_record.new_node_innovation(),
-                NodeType::Input,
-                1,
-                0.0,
-                0.0,
-            ));
+        // Input, bias and output node ids are positional so every genome built from any record
+        // shares them, keeping the innovation numbers of the initial connections consistent
+        for i in 0..inputs {
+            genome
+                .node
+                .push(NodeGene::new(i, NodeType::Input, 1, 0.0, 0.0));
         }
         // Push bias node
-        let bias_id = innovation_record.new_node_innovation();
+        let bias_id = inputs;
         genome
             .node
             .push(NodeGene::new(bias_id, NodeType::Bias, 1, 0.0, 0.0));
         genome.bias_node = bias_id;
-        for _ in 0..outputs {
-            genome.node.push(NodeGene::new(
-                innovation_record.new_node_innovation(),
-                NodeType::Output,
-                2,
-                0.0,
-                0.0,
-            ));
+        for i in 0..outputs {
+            let mut node = NodeGene::new(bias_id + 1 + i, NodeType::Output, 2, 0.0, 0.0);
+            node.activation = match &config.output_activation_functions {
+                Some(functions) => functions[i],
+                None => config.output_activation_function,
+            };
+            genome.node.push(node);
         }
+        innovation_record.num_nodes = max(innovation_record.num_nodes, genome.node.len());
 
-        genome.fully_connect(innovation_record);
+        genome.fully_connect(innovation_record, config, rng);
+        if !config.use_bias || config.bias_to_hidden_only {
+            genome.genes.retain(|gene| gene.in_node != bias_id);
+        }
+        for &index in &config.no_bias_outputs {
+            genome.remove_bias_connection(bias_id + 1 + index);
+        }
         genome
     }
 
@@ -75,13 +164,14 @@ impl Genome {
             bias_node: bias_id,
             fitness: 0.0,
             adj_fitness: 0.0,
+            behavior: None,
         }
     }
 
-    pub fn crossover(&mut self, other: Genome) -> Genome {
+    // Disjoint and excess genes are inherited from `self`, which should be the fitter parent
+    pub fn crossover(&mut self, other: Genome, config: &NeatConfig, rng: &mut dyn RngCore) -> Genome {
         let mut child = self.clone();
         child.genes.clear();
-        let mut rng = rand::thread_rng();
 
         for i in 0..self.genes.len() {
             match self.matching_gene(&other, self.genes[i].innovation) {
@@ -89,6 +179,11 @@ impl Genome {
                     let cloned_gene = self.genes[i].clone();
                     child.genes.push(cloned_gene);
                 }
+                Some(gene) if config.crossover_mode == CrossoverMode::AverageWeights => {
+                    let mut averaged_gene = self.genes[i];
+                    averaged_gene.weight = (self.genes[i].weight + gene.weight) / 2.0;
+                    child.genes.push(averaged_gene);
+                }
                 Some(gene) => {
                     if rng.gen::<f64>() < 0.5 {
                         let cloned_gene = self.genes[i].clone();
@@ -101,84 +196,271 @@ impl Genome {
             }
         }
 
+        if config.crossover_node_parameters {
+            for node in &mut child.node {
+                if let Some(other_node) = get_node(node.id, &other.node) {
+                    if rng.gen::<f64>() < 0.5 {
+                        node.activation = other_node.activation;
+                        node.param = other_node.param;
+                    }
+                }
+            }
+        }
+
+        child
+    }
+
+    // Child of several parents: every connection any parent has is inherited from the fittest
+    // parent that has it. Like `crossover`, parents of equal fitness are ordered by size when
+    // `crossover_equal_fitness_prefer_smaller` is set, and `crossover_mode` and
+    // `crossover_node_parameters` decide what is taken from which of the parents that share a gene
+    // or node. Connections that would close a loop with ones already inherited are skipped.
+    // Panics if `parents` is empty
+    pub fn multi_crossover(parents: &[&Genome], config: &NeatConfig, rng: &mut dyn RngCore) -> Genome {
+        let mut parents = parents.to_vec();
+        parents.sort_by(|a, b| {
+            a.cmp(b).then_with(|| {
+                if config.crossover_equal_fitness_prefer_smaller {
+                    a.genes.len().cmp(&b.genes.len())
+                } else {
+                    Ordering::Equal
+                }
+            })
+        });
+        let mut child = parents[0].clone();
+        child.genes.clear();
+
+        for parent in &parents {
+            for gene in &parent.genes {
+                if child.genes.iter().any(|inherited| inherited.innovation == gene.innovation)
+                    || child.reaches(gene.out_node, gene.in_node)
+                {
+                    continue;
+                }
+                for id in [gene.in_node, gene.out_node] {
+                    if get_node(id, &child.node).is_none() {
+                        child.node.push(get_node(id, &parent.node).unwrap().clone());
+                    }
+                }
+                let matching: Vec<&ConnectionGene> = parents
+                    .iter()
+                    .filter_map(|parent| {
+                        parent.genes.iter().find(|other| other.innovation == gene.innovation)
+                    })
+                    .collect();
+                let inherited = match config.crossover_mode {
+                    CrossoverMode::AverageWeights => {
+                        let mut averaged = *gene;
+                        averaged.weight =
+                            matching.iter().map(|gene| gene.weight).sum::<f64>() / matching.len() as f64;
+                        averaged
+                    }
+                    CrossoverMode::RandomParent => **matching.choose(rng).unwrap(),
+                };
+                child.genes.push(inherited);
+            }
+        }
+
+        if config.crossover_node_parameters {
+            for node in &mut child.node {
+                let sharing: Vec<&NodeGene> =
+                    parents.iter().filter_map(|parent| get_node(node.id, &parent.node)).collect();
+                if let Some(chosen) = sharing.choose(rng) {
+                    node.activation = chosen.activation;
+                    node.param = chosen.param;
+                }
+            }
+        }
+        child.recalculate_layers();
         child
     }
 
+    // Whether a path of connections, enabled or not, leads from `from` to `to`
+    fn reaches(&self, from: usize, to: usize) -> bool {
+        let mut stack = vec![from];
+        let mut visited = HashSet::new();
+        while let Some(id) = stack.pop() {
+            if id == to {
+                return true;
+            }
+            if visited.insert(id) {
+                stack.extend(self.genes.iter().filter(|gene| gene.in_node == id).map(|gene| gene.out_node));
+            }
+        }
+        false
+    }
+
     // Returns matching connection gene if exists
     fn matching_gene<'a>(&'a self, other: &'a Genome, id: usize) -> Option<&ConnectionGene> {
         let gene = other.genes.iter().find(|gene| gene.innovation == id);
         gene
     }
 
-    pub fn mutate(&mut self, innovation_record: &mut InnovationRecord) {
-        let mut rng = rand::thread_rng();
-        // Mutate weights 80%
-        if rng.gen::<f64>() < 0.7 {
-            for gene in &mut self.genes {
-                gene.mutate_weight();
+    pub fn mutate(&mut self, innovation_record: &mut InnovationRecord, config: &NeatConfig, rng: &mut dyn RngCore) {
+        // Mutate weights
+        if config.per_connection_weight_mutation {
+            for gene in self.genes.iter_mut().filter(|gene| !gene.frozen) {
+                if rng.gen::<f64>() < config.weight_mutation_prob {
+                    gene.mutate_weight(&config.weight_init, rng);
+                }
+            }
+            if rng.gen::<f64>() < config.weight_mutation_prob {
+                self.mutate_node_parameters(config, rng);
             }
+        } else if rng.gen::<f64>() < config.weight_mutation_prob {
+            for gene in self.genes.iter_mut().filter(|gene| !gene.frozen) {
+                gene.mutate_weight(&config.weight_init, rng);
+            }
+            self.mutate_node_parameters(config, rng);
+        }
+        // Mutate activation
+        if rng.gen::<f64>() < config.activation_mutation_prob {
+            self.mutate_activation(config, rng);
         }
-        // Mutate add node 5%
-        if rng.gen::<f64>() < 0.2 {
-            self.add_node(innovation_record);
+        // Mutate add node
+        if rng.gen::<f64>() < config.new_node_prob {
+            self.add_node(innovation_record, config, rng);
         }
-        // Mutate add connection 5%
-        if rng.gen::<f64>() < 0.5 {
-            self.add_connection(innovation_record);
+        // Mutate add connection
+        if rng.gen::<f64>() < config.new_connection_prob {
+            self.add_connection(innovation_record, config, rng);
         }
+        // Top up genomes below the minimum hidden node count
+        while self.num_hidden_nodes() < config.min_hidden_nodes {
+            let nodes = self.node.len();
+            self.add_node(innovation_record, config, rng);
+            if self.node.len() == nodes {
+                break;
+            }
+        }
+        debug_assert_eq!(self.validate(), Ok(()));
     }
 
-    pub fn add_connection(&mut self, innovation_record: &mut InnovationRecord) {
-        // Just try a certain amount of times to find a connection
-        let mut rng = rand::thread_rng();
-        'outer: for _ in 0..20 {
-            // Select two nodes
-            let mut node_1 = self.node[rng.gen_range(0..self.node.len())].clone();
-            let mut node_2 = self.node[rng.gen_range(0..self.node.len())].clone();
-
-            if node_1.id == node_2.id {
-                continue;
+    // Perturbs the slope of PReLU nodes, keeping it within the configured range
+    pub fn mutate_node_parameters(&mut self, config: &NeatConfig, rng: &mut dyn RngCore) {
+        let (low, high) = config.prelu_slope_range;
+        for node in &mut self.node {
+            if node.activation == ActivationFunction::PReLU {
+                node.param = (node.param + rng.gen_range(-0.1..0.1)).clamp(low, high);
             }
+        }
+    }
 
-            if node_1.node_layer == node_2.node_layer || node_1.node_layer > node_2.node_layer {
-                continue;
-            }
+    // Switches a random hidden node to a random one of the configured activation functions
+    pub fn mutate_activation(&mut self, config: &NeatConfig, rng: &mut dyn RngCore) {
+        let hidden: Vec<usize> = (0..self.node.len())
+            .filter(|&index| self.node[index].node_type == NodeType::Hidden)
+            .collect();
+        if let (Some(&index), Some(&activation)) =
+            (hidden.choose(rng), config.activation_functions.choose(rng))
+        {
+            self.node[index].activation = activation;
+        }
+    }
 
-            // Check if connection already exists
-            match self
-                .genes
-                .iter_mut()
-                .find(|gene| gene.in_node == node_1.id && gene.out_node == node_2.id)
-            {
-                None => {
-                    // Do nothing
-                }
-                Some(connection) => {
-                    if !connection.enabled {
-                        connection.enabled = true;
-                        break 'outer;
-                    } else {
-                        continue 'outer;
+    pub fn add_connection(
+        &mut self,
+        innovation_record: &mut InnovationRecord,
+        config: &NeatConfig,
+        rng: &mut dyn RngCore,
+    ) {
+        if config.max_connections.is_some_and(|max| self.genes.len() >= max) {
+            return;
+        }
+        // Small genomes choose among every possible connection so the mutation only fails when the
+        // genome is fully connected
+        if self.node.len() <= config.exhaustive_connection_max_nodes {
+            let mut candidates = vec![];
+            for node_1 in &self.node {
+                for node_2 in &self.node {
+                    if self.can_connect(node_1, node_2, config) {
+                        candidates.push((node_1.id, node_2.id));
                     }
                 }
-            };
+            }
+            if let Some(&(from, to)) = candidates.choose(rng) {
+                self.connect(from, to, innovation_record, config, rng);
+            }
+            return;
+        }
+
+        // Just try a certain amount of times to find a connection
+        for _ in 0..config.connection_mutation_attempts {
+            // Select two nodes
+            let node_1 = &self.node[rng.gen_range(0..self.node.len())];
+            let node_2 = &self.node[rng.gen_range(0..self.node.len())];
+            if self.can_connect(node_1, node_2, config) {
+                self.connect(node_1.id, node_2.id, innovation_record, config, rng);
+                break;
+            }
+        }
+    }
+
+    // Whether a connection from `node_1` to `node_2` can be added or re-enabled
+    fn can_connect(&self, node_1: &NodeGene, node_2: &NodeGene, config: &NeatConfig) -> bool {
+        if node_1.node_layer >= node_2.node_layer {
+            return false;
+        }
+
+        if node_1.node_type == NodeType::Bias
+            && (!config.use_bias || (config.bias_to_hidden_only && node_2.node_type != NodeType::Hidden))
+        {
+            return false;
+        }
+        if node_1.node_type == NodeType::Bias
+            && node_2.node_type == NodeType::Output
+            && config.no_bias_outputs.contains(&(node_2.id - self.bias_node - 1))
+        {
+            return false;
+        }
+
+        // Existing connections can only be re-enabled, and not while frozen
+        !self
+            .genes
+            .iter()
+            .any(|gene| gene.in_node == node_1.id && gene.out_node == node_2.id && (gene.enabled || gene.frozen))
+    }
 
-            // Add connection
-            let connection = ConnectionGene::new(
-                node_1.id,
-                node_2.id,
-                rng.gen_range(-5.0..5.0),
-                innovation_record.new_innovation(node_1.id, node_2.id),
-            );
-            self.genes.push(connection);
-            break 'outer;
+    fn connect(
+        &mut self,
+        from: usize,
+        to: usize,
+        innovation_record: &mut InnovationRecord,
+        config: &NeatConfig,
+        rng: &mut dyn RngCore,
+    ) {
+        match self
+            .genes
+            .iter_mut()
+            .find(|gene| gene.in_node == from && gene.out_node == to)
+        {
+            Some(connection) => connection.enabled = true,
+            None => {
+                let connection = ConnectionGene::new(
+                    from,
+                    to,
+                    config.weight_init.sample(rng),
+                    innovation_record.new_innovation(from, to),
+                );
+                self.genes.push(connection);
+            }
         }
     }
 
-    pub fn add_node(&mut self, innovation_record: &mut InnovationRecord) {
-        let mut rng = rand::thread_rng();
-        let genes_len = self.genes.len();
-        let connection = &mut self.genes[rng.gen_range(0..genes_len)];
+    pub fn add_node(&mut self, innovation_record: &mut InnovationRecord, config: &NeatConfig, rng: &mut dyn RngCore) {
+        // Splitting a connection adds a node and two connections
+        if self.genes.is_empty()
+            || config.max_nodes.is_some_and(|max| self.node.len() >= max)
+            || config.max_connections.is_some_and(|max| self.genes.len() + 2 > max)
+        {
+            return;
+        }
+        // Splitting disables the connection, so frozen connections are left alone
+        let splittable: Vec<usize> = (0..self.genes.len()).filter(|&index| !self.genes[index].frozen).collect();
+        let Some(&index) = splittable.choose(rng) else {
+            return;
+        };
+        let connection = &mut self.genes[index];
         connection.enabled = false;
         let old_weight = connection.weight;
 
@@ -188,13 +470,10 @@ impl Genome {
         let from_layer = get_node(connection_ids.0, &mut self.node.clone())
             .unwrap()
             .node_layer;
-        self.node.push(NodeGene::new(
-            node_id,
-            NodeType::Hidden,
-            from_layer + 1,
-            0.0,
-            0.0,
-        ));
+        let mut node = NodeGene::new(node_id, NodeType::Hidden, from_layer + 1, 0.0, 0.0);
+        node.activation = config.default_activation_function;
+        node.param = config.leaky_relu_slope;
+        self.node.push(node);
         self.genes.push(ConnectionGene::new(
             connection_ids.0,
             node_id,
@@ -204,10 +483,99 @@ impl Genome {
         self.genes.push(ConnectionGene::new(
             node_id,
             connection_ids.1,
-            rng.gen_range(-5.0..5.0),
+            config.weight_init.sample(rng),
             innovation_record.new_innovation(node_id, connection_ids.1),
         ));
-        // Recalculate layers
+        self.recalculate_layers();
+    }
+
+    // Gives hidden nodes fresh ids from `innovation_record` and renumbers connections to match, so a
+    // genome built with another record can be mixed with genomes built with this one
+    pub fn remap_innovations(&mut self, innovation_record: &mut InnovationRecord) {
+        let ids = self
+            .node
+            .iter()
+            .filter(|node| node.node_type == NodeType::Hidden)
+            .map(|node| (node.id, innovation_record.new_node_innovation()))
+            .collect();
+        self.renumber_nodes(&ids, innovation_record);
+    }
+
+    // Gives nodes the ids in `ids`, keeping ids it has no entry for, and renumbers connections to
+    // match with `innovation_record`
+    pub(crate) fn renumber_nodes(&mut self, ids: &HashMap<usize, usize>, innovation_record: &mut InnovationRecord) {
+        for node in &mut self.node {
+            node.id = *ids.get(&node.id).unwrap_or(&node.id);
+        }
+        for gene in &mut self.genes {
+            gene.in_node = *ids.get(&gene.in_node).unwrap_or(&gene.in_node);
+            gene.out_node = *ids.get(&gene.out_node).unwrap_or(&gene.out_node);
+            gene.innovation = innovation_record.new_innovation(gene.in_node, gene.out_node);
+        }
+    }
+
+    // Copy with hidden nodes numbered by structure rather than by when they evolved: layer by
+    // layer, ordered by their incoming connections, and connections numbered in (in, out) order.
+    // Genomes that differ only in numbering canonicalize to the same genes. The innovation numbers
+    // no longer match any record, so the copy is for comparison rather than further evolution
+    pub fn canonicalize(&self) -> Genome {
+        let mut ids: HashMap<usize, usize> = self
+            .node
+            .iter()
+            .filter(|node| node.node_type != NodeType::Hidden)
+            .map(|node| (node.id, node.id))
+            .collect();
+        let mut hidden: Vec<&NodeGene> = self
+            .node
+            .iter()
+            .filter(|node| node.node_type == NodeType::Hidden)
+            .collect();
+        hidden.sort_by_key(|node| node.node_layer);
+
+        let mut next_id = self.inputs + self.outputs;
+        for layer in hidden.chunk_by(|a, b| a.node_layer == b.node_layer) {
+            // Sources in earlier layers already have their new ids
+            let mut keyed: Vec<_> = layer
+                .iter()
+                .map(|node| {
+                    let mut incoming: Vec<(usize, u64, bool)> = self
+                        .genes
+                        .iter()
+                        .filter(|gene| gene.out_node == node.id)
+                        .map(|gene| {
+                            let source = ids.get(&gene.in_node).copied().unwrap_or(usize::MAX);
+                            (source, gene.weight.to_bits(), gene.enabled)
+                        })
+                        .collect();
+                    incoming.sort_unstable();
+                    (incoming, node.id)
+                })
+                .collect();
+            keyed.sort_unstable();
+            for (_, id) in keyed {
+                ids.insert(id, next_id);
+                next_id += 1;
+            }
+        }
+
+        let mut genome = self.clone();
+        for node in &mut genome.node {
+            node.id = ids[&node.id];
+        }
+        genome.node.sort_by_key(|node| node.id);
+        for gene in &mut genome.genes {
+            gene.in_node = ids[&gene.in_node];
+            gene.out_node = ids[&gene.out_node];
+        }
+        genome.genes.sort_by_key(|gene| (gene.in_node, gene.out_node));
+        for (innovation, gene) in genome.genes.iter_mut().enumerate() {
+            gene.innovation = innovation;
+        }
+        genome
+    }
+
+    // Places every non-input node one layer past its deepest incoming connection
+    pub(crate) fn recalculate_layers(&mut self) {
         let nodes = self.node.clone();
         let genes = self.genes.clone();
         for node in &mut self.node {
@@ -219,7 +587,218 @@ impl Genome {
         self.layers = self.node.iter().map(|node| node.node_layer).max().unwrap();
     }
 
-    pub fn fully_connect(&mut self, innovation_record: &mut InnovationRecord) {
+    // Input nodes, not counting the bias
+    pub fn num_inputs(&self) -> usize {
+        self.inputs - 1
+    }
+
+    pub fn num_outputs(&self) -> usize {
+        self.outputs
+    }
+
+    pub fn num_hidden_nodes(&self) -> usize {
+        self.node
+            .iter()
+            .filter(|node| node.node_type == NodeType::Hidden)
+            .count()
+    }
+
+    // Weight of the connection with the given innovation number
+    pub fn weight(&self, innovation: usize) -> Option<f64> {
+        self.genes
+            .iter()
+            .find(|gene| gene.innovation == innovation)
+            .map(|gene| gene.weight)
+    }
+
+    // Returns whether the genome has the connection
+    pub fn set_weight(&mut self, innovation: usize, weight: f64) -> bool {
+        match self.genes.iter_mut().find(|gene| gene.innovation == innovation) {
+            Some(gene) => {
+                gene.weight = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Enables a disabled connection or disables an enabled one, returns whether the genome has it
+    pub fn toggle_connection(&mut self, innovation: usize) -> bool {
+        match self.genes.iter_mut().find(|gene| gene.innovation == innovation) {
+            Some(gene) => {
+                gene.enabled = !gene.enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Locks a connection's weight and enabled state against mutation, returns whether the genome
+    // has it
+    pub fn freeze_connection(&mut self, innovation: usize) -> bool {
+        self.set_frozen(innovation, true)
+    }
+
+    pub fn unfreeze_connection(&mut self, innovation: usize) -> bool {
+        self.set_frozen(innovation, false)
+    }
+
+    fn set_frozen(&mut self, innovation: usize, frozen: bool) -> bool {
+        match self.genes.iter_mut().find(|gene| gene.innovation == innovation) {
+            Some(gene) => {
+                gene.frozen = frozen;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Removes the connection from the bias node to `output_id`, returns whether the genome had it
+    pub fn remove_bias_connection(&mut self, output_id: usize) -> bool {
+        let len = self.genes.len();
+        self.genes
+            .retain(|gene| !(gene.in_node == self.bias_node && gene.out_node == output_id));
+        self.genes.len() < len
+    }
+
+    pub fn num_enabled_connections(&self) -> usize {
+        self.genes.iter().filter(|gene| gene.enabled).count()
+    }
+
+    // Summed number of layers spanned by the enabled connections
+    pub fn wiring_cost(&self) -> f64 {
+        let layer = |id: usize| get_node(id, &self.node).unwrap().node_layer;
+        self.genes
+            .iter()
+            .filter(|gene| gene.enabled)
+            .map(|gene| layer(gene.out_node).abs_diff(layer(gene.in_node)) as f64)
+            .sum()
+    }
+
+    // Fitness genomes are ranked by when selecting parents and sharing offspring: the raw fitness
+    // less the wiring cost scaled by `config.wiring_cost_coefficient`. `fitness` itself is kept
+    pub fn selection_fitness(&self, config: &NeatConfig) -> f64 {
+        if config.wiring_cost_coefficient == 0.0 {
+            return self.fitness;
+        }
+        self.fitness - config.wiring_cost_coefficient * self.wiring_cost()
+    }
+
+    pub fn num_disabled_connections(&self) -> usize {
+        self.genes.iter().filter(|gene| !gene.enabled).count()
+    }
+
+    // Number of enabled connections on the longest path from an input or bias node to an output,
+    // 0 if no output can be reached
+    pub fn max_depth(&self) -> usize {
+        // Layers order every connection from a lower to a higher layer
+        let mut nodes: Vec<&NodeGene> = self.node.iter().collect();
+        nodes.sort_by_key(|node| node.node_layer);
+
+        let mut depth: HashMap<usize, usize> = HashMap::new();
+        for node in nodes {
+            if node.node_type == NodeType::Input || node.node_type == NodeType::Bias {
+                depth.insert(node.id, 0);
+                continue;
+            }
+            let node_depth = self
+                .genes
+                .iter()
+                .filter(|gene| gene.enabled && gene.out_node == node.id)
+                .filter_map(|gene| depth.get(&gene.in_node))
+                .max();
+            if let Some(node_depth) = node_depth {
+                depth.insert(node.id, node_depth + 1);
+            }
+        }
+
+        self.node
+            .iter()
+            .filter(|node| node.node_type == NodeType::Output)
+            .filter_map(|node| depth.get(&node.id))
+            .max()
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // Up to `max_paths` simple paths of node ids from an input to an output over enabled
+    // connections, showing which inputs influence which outputs. The number of paths can grow
+    // exponentially with the number of hidden layers
+    pub fn active_paths(&self, max_paths: usize) -> Vec<Vec<usize>> {
+        let mut paths = vec![];
+        let mut stack: Vec<Vec<usize>> = self
+            .node
+            .iter()
+            .rev()
+            .filter(|node| node.node_type == NodeType::Input)
+            .map(|node| vec![node.id])
+            .collect();
+        while let Some(path) = stack.pop() {
+            if paths.len() == max_paths {
+                break;
+            }
+            let last = *path.last().unwrap();
+            for gene in self.genes.iter().rev() {
+                if gene.enabled && gene.in_node == last && !path.contains(&gene.out_node) {
+                    let mut next = path.clone();
+                    next.push(gene.out_node);
+                    stack.push(next);
+                }
+            }
+            if self.node.iter().any(|node| node.id == last && node.node_type == NodeType::Output) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    // Removes hidden nodes without an enabled path to an output, along with their connections.
+    // Disabled connections between the remaining nodes are kept as they can still be re-enabled
+    pub fn prune(&mut self) {
+        // Walk backwards from the outputs over enabled connections
+        let mut alive: HashSet<usize> = self
+            .node
+            .iter()
+            .filter(|node| node.node_type != NodeType::Hidden)
+            .map(|node| node.id)
+            .collect();
+        let mut stack: Vec<usize> = self
+            .node
+            .iter()
+            .filter(|node| node.node_type == NodeType::Output)
+            .map(|node| node.id)
+            .collect();
+        while let Some(id) = stack.pop() {
+            for gene in self.genes.iter().filter(|gene| gene.enabled && gene.out_node == id) {
+                if alive.insert(gene.in_node) {
+                    stack.push(gene.in_node);
+                }
+            }
+        }
+
+        self.node.retain(|node| alive.contains(&node.id));
+        self.genes
+            .retain(|gene| alive.contains(&gene.in_node) && alive.contains(&gene.out_node));
+        // Removing nodes and connections keeps the existing layer order valid
+        self.layers = self.node.iter().map(|node| node.node_layer).max().unwrap();
+    }
+
+    // Disables connections with weights closer to zero than `threshold`, keeping them for their
+    // innovation history
+    pub fn prune_weak_connections(&mut self, threshold: f64) {
+        for gene in self.genes.iter_mut().filter(|gene| !gene.frozen) {
+            if gene.weight.abs() < threshold {
+                gene.enabled = false;
+            }
+        }
+    }
+
+    pub fn fully_connect(
+        &mut self,
+        innovation_record: &mut InnovationRecord,
+        config: &NeatConfig,
+        rng: &mut dyn RngCore,
+    ) {
         // If there are hidden nodes
         if self.node.len() > self.inputs + self.outputs {
             for i in 0..self.inputs {
@@ -227,7 +806,7 @@ impl Genome {
                     self.genes.push(ConnectionGene::new(
                         self.node[i].id,
                         self.node[j].id,
-                        rand::thread_rng().gen_range(-5.0..5.0),
+                        config.weight_init.sample(rng),
                         innovation_record.new_innovation(i, j),
                     ));
                 }
@@ -237,7 +816,7 @@ impl Genome {
                     self.genes.push(ConnectionGene::new(
                         self.node[i].id,
                         self.node[self.inputs + j].id,
-                        rand::thread_rng().gen_range(-5.0..5.0),
+                        config.weight_init.sample(rng),
                         innovation_record.new_innovation(i, self.inputs + j),
                     ));
                 }
@@ -248,7 +827,7 @@ impl Genome {
                     self.genes.push(ConnectionGene::new(
                         self.node[i].id,
                         self.node[self.inputs + j].id,
-                        rand::thread_rng().gen_range(-5.0..5.0),
+                        config.weight_init.sample(rng),
                         innovation_record.new_innovation(i, self.inputs + j),
                     ));
                 }
@@ -256,7 +835,11 @@ impl Genome {
         }
     }
 
-    pub fn feed_forward(&mut self, inputs: Vec<f64>) -> Vec<f64> {
+    // Output i is the value of output node `num_inputs() + 1 + i`, the same for `compile`. Custom
+    // activations and the output clamp are taken from `config`. Panics if there isn't one value
+    // per input node, not counting the bias
+    pub fn feed_forward(&mut self, inputs: Vec<f64>, config: &NeatConfig) -> Vec<f64> {
+        assert_inputs(self.num_inputs(), inputs.len());
         // Reset
         for node in &mut self.node {
             node.sum_inputs = 0.0;
@@ -296,73 +879,376 @@ impl Genome {
                         .position(|node| node.id == node_id.clone())
                         .unwrap();
                     self.node[node_index].sum_inputs = node.sum_inputs;
-                    self.node[node_index].sum_outputs =
-                        1.0 / (1.0 + (-4.9 * node.sum_inputs).exp());
+                    self.node[node_index].sum_outputs = node.activation.activate_with_param(
+                        node.sum_inputs,
+                        node.param,
+                        &config.custom_activation_functions,
+                    );
                 }
             }
         }
 
         // Get output nodes
-        let mut outputs = vec![];
-        for node in &mut self.node {
-            if node.node_type == NodeType::Output {
-                outputs.push(node.sum_outputs);
-            }
-        }
-        outputs
+        self.output_indices()
+            .into_iter()
+            .map(|index| clamp_output(self.node[index].sum_outputs, config.output_clamp))
+            .collect()
     }
 
-    pub fn compatability_distance(&self, other: &Self) -> f64 {
-        // let c1 = 1.0;
-        let c2 = 1.0;
-        let c3 = 0.4;
-
-        let n1 = self.genes.len() as f64;
-        let n2 = other.genes.len() as f64;
-        let n = f64::max(n1, n2);
+    // Positions in `node` of the output nodes by id, so output i of a network is always node
+    // `num_inputs() + 1 + i` however the nodes are ordered
+    fn output_indices(&self) -> Vec<usize> {
+        let mut outputs: Vec<usize> = (0..self.node.len())
+            .filter(|&index| self.node[index].node_type == NodeType::Output)
+            .collect();
+        outputs.sort_by_key(|&index| self.node[index].id);
+        debug_assert!(outputs
+            .iter()
+            .enumerate()
+            .all(|(i, &index)| self.node[index].id == self.bias_node + 1 + i));
+        outputs
+    }
 
-        if n == 0.0 {
-            return 0.0;
+    // Checks the structure is consistent: input, bias and output nodes exist, connections join
+    // existing nodes without leading into inputs, and each pair of nodes is connected at most once
+    pub fn validate(&self) -> Result<(), GenomeError> {
+        for id in 0..self.inputs + self.outputs {
+            if get_node(id, &self.node).is_none() {
+                return Err(GenomeError::MissingNode(id));
+            }
+        }
+        let mut connections = HashSet::new();
+        for gene in &self.genes {
+            let (Some(_), Some(to)) = (get_node(gene.in_node, &self.node), get_node(gene.out_node, &self.node))
+            else {
+                return Err(GenomeError::DanglingConnection(gene.innovation));
+            };
+            if to.node_type == NodeType::Input || to.node_type == NodeType::Bias {
+                return Err(GenomeError::ConnectionIntoInput(gene.innovation));
+            }
+            if !connections.insert((gene.in_node, gene.out_node)) {
+                return Err(GenomeError::DuplicateConnection(gene.in_node, gene.out_node));
+            }
         }
+        Ok(())
+    }
 
-        let matching_genes = self.genes
+    // Node ids ordered so every node comes after the sources of its enabled connections
+    pub fn topological_order(&self) -> Result<Vec<usize>, GenomeError> {
+        let mut incoming: HashMap<usize, usize> = self.node.iter().map(|node| (node.id, 0)).collect();
+        for gene in self.genes.iter().filter(|gene| gene.enabled) {
+            *incoming.get_mut(&gene.out_node).unwrap() += 1;
+        }
+        // Kahn's algorithm, starting from nodes in their order in the genome
+        let mut ready: Vec<usize> = self
+            .node
             .iter()
-            .filter(|gene| other.genes.iter().any(|other_gene| other_gene.innovation == gene.innovation))
-            .collect::<Vec<&ConnectionGene>>();
+            .rev()
+            .filter(|node| incoming[&node.id] == 0)
+            .map(|node| node.id)
+            .collect();
+        let mut order = Vec::with_capacity(self.node.len());
+        while let Some(id) = ready.pop() {
+            order.push(id);
+            for gene in self.genes.iter().filter(|gene| gene.enabled && gene.in_node == id) {
+                let count = incoming.get_mut(&gene.out_node).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(gene.out_node);
+                }
+            }
+        }
+        if order.len() == self.node.len() {
+            Ok(order)
+        } else {
+            Err(GenomeError::CycleDetected)
+        }
+    }
 
-        let disjoint_num = n1 + n2 - (2 * matching_genes.len()) as f64;
+    // Flattens the network into index based arrays and returns an owned closure that computes the
+    // same outputs as `feed_forward` without any per call node lookups
+    pub fn compile(&self, config: &NeatConfig) -> impl Fn(&[f64]) -> Vec<f64> {
+        let index_of: HashMap<usize, usize> = self
+            .node
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.id, index))
+            .collect();
 
-        let avg_weight_diff = matching_genes.iter()
-            .fold(0.0, |acc, gene| acc +
-                (gene.weight - other.genes.iter().find(|other_gene|
-                    other_gene.innovation == gene.innovation)
-                    .unwrap()
-                    .weight)
-                    .abs())
-            / matching_genes.len() as f64;
+        // Nodes past the input layer in evaluation order, each with its (source index, weight) pairs
+        let mut order: Vec<usize> = (0..self.node.len())
+            .filter(|&index| self.node[index].node_layer >= 2)
+            .collect();
+        order.sort_by_key(|&index| self.node[index].node_layer);
+        let activations: Vec<(ActivationFunction, f64)> = self
+            .node
+            .iter()
+            .map(|node| (node.activation, node.param))
+            .collect();
+        let incoming: Vec<(usize, Vec<(usize, f64)>)> = order
+            .into_iter()
+            .map(|index| {
+                let sources = self
+                    .genes
+                    .iter()
+                    .filter(|gene| gene.enabled && gene.out_node == self.node[index].id)
+                    .map(|gene| (index_of[&gene.in_node], gene.weight))
+                    .collect();
+                (index, sources)
+            })
+            .collect();
+        let outputs = self.output_indices();
+        let node_count = self.node.len();
+        let bias_node = self.bias_node;
+        let custom_activations = config.custom_activation_functions.clone();
+        let output_clamp = config.output_clamp;
+        let input_count = self.num_inputs();
 
-        (c2 * disjoint_num) / n + (c3 * avg_weight_diff)
+        move |inputs: &[f64]| {
+            assert_inputs(input_count, inputs.len());
+            let mut values = vec![0.0; node_count];
+            values[..inputs.len()].copy_from_slice(inputs);
+            values[bias_node] = 1.0;
+            for (index, sources) in &incoming {
+                let sum: f64 = sources
+                    .iter()
+                    .map(|&(source, weight)| values[source] * weight)
+                    .sum();
+                let (activation, param) = activations[*index];
+                values[*index] = activation.activate_with_param(sum, param, &custom_activations);
+            }
+            outputs
+                .iter()
+                .map(|&index| clamp_output(values[index], output_clamp))
+                .collect()
+        }
     }
-}
 
-fn get_node(id: usize, nodes: &Vec<NodeGene>) -> Option<&NodeGene> {
-    let node = nodes.iter().find(|node| node.id == id);
-    match node {
-        None => None,
-        Some(node) => Some(node),
+    pub fn compatability_distance(&self, other: &Self, config: &NeatConfig) -> f64 {
+        self.compatibility_breakdown(other, config).total_distance
     }
-}
 
-fn find_layer(
-    nodes: &Vec<NodeGene>,
-    genes: &Vec<ConnectionGene>,
-    node: Option<&NodeGene>,
-) -> usize {
-    match node {
-        None => 0,
-        Some(node) => {
-            // Get all connections to node
-            let connections: Vec<&ConnectionGene> = genes
+    // Terms of the compatibility distance, to see why two genomes are or aren't compatible
+    pub fn compatibility_breakdown(&self, other: &Self, config: &NeatConfig) -> CompatibilityBreakdown {
+        // let c1 = 1.0;
+        let c2 = 1.0;
+        let c3 = 0.4;
+
+        let (genes, other_genes) = if config.compatibility_ignore_disabled {
+            // Genes disabled in either genome are left out of both
+            let disabled: HashSet<usize> = self
+                .genes
+                .iter()
+                .chain(&other.genes)
+                .filter(|gene| !gene.enabled)
+                .map(|gene| gene.innovation)
+                .collect();
+            let enabled = |genome: &Genome| -> Cow<[ConnectionGene]> {
+                genome
+                    .genes
+                    .iter()
+                    .filter(|gene| !disabled.contains(&gene.innovation))
+                    .copied()
+                    .collect()
+            };
+            (enabled(self), enabled(other))
+        } else {
+            (Cow::Borrowed(&self.genes[..]), Cow::Borrowed(&other.genes[..]))
+        };
+
+        let n1 = genes.len() as f64;
+        let n2 = other_genes.len() as f64;
+        let n = f64::max(n1, n2);
+        let normalizer = if n < config.compatibility_normalization_threshold as f64 {
+            1.0
+        } else {
+            n
+        };
+
+        let diff = diff_genes(&genes, &other_genes);
+        // Genomes without matching genes differ only structurally
+        let avg_weight_diff = if diff.matching.is_empty() {
+            0.0
+        } else {
+            diff.weight_deltas.iter().fold(0.0, |acc, delta| acc + delta.abs()) / diff.matching.len() as f64
+        };
+        let connection_distance = if n == 0.0 {
+            0.0
+        } else {
+            let disjoint_num = (diff.disjoint.len() + diff.excess.len()) as f64;
+            (c2 * disjoint_num) / normalizer + (c3 * avg_weight_diff)
+        };
+        let activation_difference = self.activation_difference(other);
+
+        CompatibilityBreakdown {
+            num_disjoint: diff.disjoint.len(),
+            num_excess: diff.excess.len(),
+            num_matching: diff.matching.len(),
+            avg_weight_diff,
+            normalizer,
+            activation_difference,
+            total_distance: connection_distance
+                + config.compatibility_activation_coefficient * activation_difference,
+        }
+    }
+
+    // Fraction of the nodes both genomes have that use different activation functions
+    fn activation_difference(&self, other: &Self) -> f64 {
+        let mut matching = 0;
+        let mut different = 0;
+        for node in &self.node {
+            if let Some(other_node) = get_node(node.id, &other.node) {
+                matching += 1;
+                if node.activation != other_node.activation {
+                    different += 1;
+                }
+            }
+        }
+        if matching == 0 {
+            0.0
+        } else {
+            different as f64 / matching as f64
+        }
+    }
+
+    // Distance used for speciation, between behaviors when `config.use_behavioral_speciation` is set
+    // and both genomes have one
+    pub fn speciation_distance(&self, other: &Self, config: &NeatConfig) -> f64 {
+        match (&self.behavior, &other.behavior) {
+            (Some(behavior), Some(other_behavior)) if config.use_behavioral_speciation => behavior
+                .iter()
+                .zip(other_behavior)
+                .fold(0.0, |acc, (a, b)| acc + (a - b).powi(2))
+                .sqrt(),
+            _ => self.compatability_distance(other, config),
+        }
+    }
+
+    pub fn diff(&self, other: &Self) -> GenomeDiff {
+        diff_genes(&self.genes, &other.genes)
+    }
+
+    // Counts of connection weights in `bins` equal-width bins spanning the smallest to largest weight
+    pub fn weight_histogram(&self, bins: usize) -> Vec<usize> {
+        let mut histogram = vec![0; bins];
+        if bins == 0 || self.genes.is_empty() {
+            return histogram;
+        }
+        let min = self.genes.iter().fold(f64::MAX, |acc, gene| acc.min(gene.weight));
+        let max = self.genes.iter().fold(f64::MIN, |acc, gene| acc.max(gene.weight));
+        for gene in &self.genes {
+            let bin = if max > min {
+                (((gene.weight - min) / (max - min)) * bins as f64) as usize
+            } else {
+                0
+            };
+            histogram[bin.min(bins - 1)] += 1;
+        }
+        histogram
+    }
+
+    // Hash of what determines the network's outputs: enabled connections by innovation with their
+    // weights, and node activations by id. Weights and parameters are rounded so genomes that only
+    // differ by float noise hash equal, and gene order doesn't matter
+    pub fn structural_hash(&self) -> u64 {
+        let round = |x: f64| (x * 1e6).round() as i64;
+        let mut genes: Vec<(usize, i64)> = self
+            .genes
+            .iter()
+            .filter(|gene| gene.enabled)
+            .map(|gene| (gene.innovation, round(gene.weight)))
+            .collect();
+        genes.sort_unstable();
+        let mut nodes: Vec<(usize, ActivationFunction, i64)> = self
+            .node
+            .iter()
+            .map(|node| (node.id, node.activation, round(node.param)))
+            .collect();
+        nodes.sort_unstable_by_key(|node| node.0);
+
+        let mut hasher = DefaultHasher::new();
+        genes.hash(&mut hasher);
+        nodes.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+fn assert_inputs(expected: usize, got: usize) {
+    assert!(expected == got, "network expects {} inputs, got {}", expected, got);
+}
+
+// Walks both genomes' genes in innovation order at once, so every list in the diff comes out sorted
+fn diff_genes(genes: &[ConnectionGene], other_genes: &[ConnectionGene]) -> GenomeDiff {
+    fn sorted(genes: &[ConnectionGene]) -> Vec<&ConnectionGene> {
+        let mut sorted: Vec<&ConnectionGene> = genes.iter().collect();
+        sorted.sort_unstable_by_key(|gene| gene.innovation);
+        sorted
+    }
+    let (genes, other_genes) = (sorted(genes), sorted(other_genes));
+    let self_max = genes.last().map(|gene| gene.innovation);
+    let other_max = other_genes.last().map(|gene| gene.innovation);
+
+    let mut diff = GenomeDiff::default();
+    let mut unmatched = |innovation: usize, other_max: Option<usize>| {
+        if Some(innovation) > other_max {
+            diff.excess.push(innovation);
+        } else {
+            diff.disjoint.push(innovation);
+        }
+    };
+    let (mut i, mut j) = (0, 0);
+    let mut matching = vec![];
+    while i < genes.len() || j < other_genes.len() {
+        match (genes.get(i), other_genes.get(j)) {
+            (Some(gene), Some(other_gene)) if gene.innovation == other_gene.innovation => {
+                matching.push((gene.innovation, gene.weight - other_gene.weight));
+                i += 1;
+                j += 1;
+            }
+            (Some(gene), Some(other_gene)) if gene.innovation < other_gene.innovation => {
+                unmatched(gene.innovation, other_max);
+                i += 1;
+            }
+            (Some(gene), None) => {
+                unmatched(gene.innovation, other_max);
+                i += 1;
+            }
+            (_, Some(other_gene)) => {
+                unmatched(other_gene.innovation, self_max);
+                j += 1;
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    (diff.matching, diff.weight_deltas) = matching.into_iter().unzip();
+    diff
+}
+
+fn clamp_output(value: f64, range: Option<(f64, f64)>) -> f64 {
+    match range {
+        Some((low, high)) => value.clamp(low, high),
+        None => value,
+    }
+}
+
+fn get_node(id: usize, nodes: &Vec<NodeGene>) -> Option<&NodeGene> {
+    let node = nodes.iter().find(|node| node.id == id);
+    match node {
+        None => None,
+        Some(node) => Some(node),
+    }
+}
+
+fn find_layer(
+    nodes: &Vec<NodeGene>,
+    genes: &Vec<ConnectionGene>,
+    node: Option<&NodeGene>,
+) -> usize {
+    match node {
+        None => 0,
+        Some(node) => {
+            // Get all connections to node
+            let connections: Vec<&ConnectionGene> = genes
                 .iter()
                 .filter(|gene| gene.out_node == node.id)
                 .collect();
@@ -420,13 +1306,47 @@ impl Ord for Genome {
     }
 }
 
+// Builds an exact topology for tests. Hidden node ids follow the outputs, so with 2 inputs and 1
+// output the inputs are 0 and 1, the bias 2, the output 3 and hidden nodes start at 4. Connections
+// are (in, out, weight, enabled) with innovations from `innovation_record`
+#[cfg(test)]
+impl Genome {
+    pub fn from_spec(
+        inputs: usize,
+        outputs: usize,
+        hidden: usize,
+        connections: &[(usize, usize, f64, bool)],
+        innovation_record: &mut InnovationRecord,
+    ) -> Self {
+        let mut genome = Self::new(inputs, outputs, innovation_record);
+        genome.genes.clear();
+        let first_hidden = genome.node.len();
+        for id in first_hidden..first_hidden + hidden {
+            genome.node.push(NodeGene::new(id, NodeType::Hidden, 2, 0.0, 0.0));
+        }
+        innovation_record.num_nodes = max(innovation_record.num_nodes, genome.node.len());
+        for &(in_node, out_node, weight, enabled) in connections {
+            let mut gene =
+                ConnectionGene::new(in_node, out_node, weight, innovation_record.new_innovation(in_node, out_node));
+            gene.enabled = enabled;
+            genome.genes.push(gene);
+        }
+        genome.recalculate_layers();
+        genome
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::WeightInit;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn setup_genome() {
         let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig::default();
         let mut genome = Genome::new(2, 1, &mut innovation_record);
         assert_eq!(genome.inputs, 3);
         assert_eq!(genome.outputs, 1);
@@ -436,7 +1356,7 @@ mod tests {
 
         // Add a bunch of mutation
         for _ in 0..16 {
-            genome.mutate(&mut innovation_record);
+            genome.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
         }
         dbg!(genome.genes);
         dbg!(genome.node);
@@ -453,17 +1373,1053 @@ mod tests {
         genome.genes[1].weight = 0.5;
         genome.genes[2].weight = 0.5;
 
-        let output = genome.feed_forward(vec![0.0, 0.0]);
+        let output = genome.feed_forward(vec![0.0, 0.0], &NeatConfig::default());
         assert_eq!(output[0], 0.6224593312018546);
-        let output = genome.feed_forward(vec![1.0, 0.0]);
+        let output = genome.feed_forward(vec![1.0, 0.0], &NeatConfig::default());
         assert_eq!(output[0], 0.7310585786300049);
-        let output = genome.feed_forward(vec![0.0, 1.0]);
+        let output = genome.feed_forward(vec![0.0, 1.0], &NeatConfig::default());
         assert_eq!(output[0], 0.7310585786300049);
-        let output = genome.feed_forward(vec![1.0, 1.0]);
+        let output = genome.feed_forward(vec![1.0, 1.0], &NeatConfig::default());
         assert_eq!(output[0], 0.8175744761936437);
         dbg!(genome);
     }
 
+    #[test]
+    fn prune_dead_ends() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig::default();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        for _ in 0..30 {
+            genome.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
+        }
+
+        // Hidden node that only receives input and never reaches the output
+        let dead_id = innovation_record.new_node_innovation();
+        genome.node.push(NodeGene::new(dead_id, NodeType::Hidden, 2, 0.0, 0.0));
+        genome.genes.push(ConnectionGene::new(
+            0,
+            dead_id,
+            1.0,
+            innovation_record.new_innovation(0, dead_id),
+        ));
+
+        let inputs = [vec![0.0, 0.0], vec![1.0, 0.0], vec![0.0, 1.0], vec![0.5, -2.0]];
+        let before: Vec<Vec<f64>> = inputs.iter().map(|i| genome.feed_forward(i.clone(), &config)).collect();
+        let (nodes, genes) = (genome.node.len(), genome.genes.len());
+
+        genome.prune();
+        assert!(genome.node.len() < nodes);
+        assert!(genome.genes.len() < genes);
+        assert!(genome.node.iter().all(|node| node.id != dead_id));
+        let after: Vec<Vec<f64>> = inputs.iter().map(|i| genome.feed_forward(i.clone(), &config)).collect();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn compiled_matches_feed_forward() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            default_activation_function: ActivationFunction::PReLU,
+            ..NeatConfig::default()
+        };
+        let mut genome = Genome::new(3, 2, &mut innovation_record);
+        for _ in 0..40 {
+            genome.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
+        }
+
+        let inputs = [vec![0.0, 0.0, 0.0], vec![1.0, 0.0, 0.5], vec![-1.0, 2.0, 0.25]];
+        let expected: Vec<Vec<f64>> = inputs.iter().map(|i| genome.feed_forward(i.clone(), &config)).collect();
+
+        // The closure owns everything it needs, so the genome can be dropped
+        let network = genome.compile(&config);
+        drop(genome);
+        for (input, output) in inputs.iter().zip(expected) {
+            assert_eq!(network(input), output);
+        }
+    }
+
+    #[test]
+    fn leaky_relu_slope() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            default_activation_function: ActivationFunction::LeakyReLU,
+            leaky_relu_slope: 0.2,
+            ..NeatConfig::default()
+        };
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        genome.add_node(&mut innovation_record, &config, &mut rand::thread_rng());
+
+        let hidden = genome.node.last().unwrap();
+        assert_eq!(hidden.activation, ActivationFunction::LeakyReLU);
+        assert_eq!(hidden.activation.activate_with_param(-1.0, hidden.param, &[]), -0.2);
+        assert_eq!(hidden.activation.activate_with_param(3.0, hidden.param, &[]), 3.0);
+    }
+
+    #[test]
+    fn prelu_slope_mutation() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            default_activation_function: ActivationFunction::PReLU,
+            prelu_slope_range: (0.0, 0.3),
+            ..NeatConfig::default()
+        };
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        genome.add_node(&mut innovation_record, &config, &mut rand::thread_rng());
+        let initial = genome.node.last().unwrap().param;
+
+        let mut changed = false;
+        for _ in 0..200 {
+            genome.mutate_node_parameters(&config, &mut rand::thread_rng());
+            let param = genome.node.last().unwrap().param;
+            assert!((0.0..=0.3).contains(&param));
+            changed |= param != initial;
+        }
+        assert!(changed);
+    }
+
+    #[test]
+    fn bias_node_output() {
+        // Only the bias connection is enabled, so the output is exactly its weight
+        let mut innovation_record = InnovationRecord::new();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        for gene in &mut genome.genes {
+            gene.enabled = gene.in_node == genome.bias_node;
+            gene.weight = 0.75;
+        }
+        genome.node[3].activation = ActivationFunction::None;
+
+        assert_eq!(genome.feed_forward(vec![0.3, 0.9], &NeatConfig::default()), vec![0.75]);
+        assert_eq!(genome.compile(&NeatConfig::default())(&[0.3, 0.9]), vec![0.75]);
+    }
+
+    #[test]
+    fn structure_caps() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            max_nodes: Some(7),
+            max_connections: Some(12),
+            ..NeatConfig::default()
+        };
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        for _ in 0..300 {
+            genome.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
+            assert!(genome.node.len() <= 7);
+            assert!(genome.genes.len() <= 12);
+        }
+
+        // Once capped, structural mutations leave the genome unchanged
+        let (nodes, genes) = (genome.node.len(), genome.genes.len());
+        let config = NeatConfig {
+            max_nodes: Some(nodes),
+            max_connections: Some(genes),
+            ..NeatConfig::default()
+        };
+        for _ in 0..50 {
+            genome.add_node(&mut innovation_record, &config, &mut rand::thread_rng());
+            genome.add_connection(&mut innovation_record, &config, &mut rand::thread_rng());
+        }
+        assert_eq!((genome.node.len(), genome.genes.len()), (nodes, genes));
+    }
+
+    #[test]
+    fn min_hidden_nodes() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            min_hidden_nodes: 3,
+            ..NeatConfig::default()
+        };
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        genome.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
+        assert!(genome.num_hidden_nodes() >= 3);
+    }
+
+    #[test]
+    fn structural_summary() {
+        let mut innovation_record = InnovationRecord::new();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        assert_eq!(genome.num_hidden_nodes(), 0);
+        assert_eq!(genome.max_depth(), 1);
+
+        // 0 -> 4 -> 5 -> 3 with input 1 only reaching the output through disabled connections
+        genome.genes.clear();
+        genome.node.push(NodeGene::new(4, NodeType::Hidden, 2, 0.0, 0.0));
+        genome.node.push(NodeGene::new(5, NodeType::Hidden, 3, 0.0, 0.0));
+        genome.node[3].node_layer = 4;
+        genome.layers = 4;
+        let connections = [
+            (0, 4, true),
+            (4, 5, true),
+            (5, 3, true),
+            (1, 3, false),
+            (2, 3, true),
+            (1, 5, false),
+        ];
+        for (i, (from, to, enabled)) in connections.into_iter().enumerate() {
+            let mut gene = ConnectionGene::new(from, to, 1.0, i);
+            gene.enabled = enabled;
+            genome.genes.push(gene);
+        }
+
+        assert_eq!(genome.num_hidden_nodes(), 2);
+        assert_eq!(genome.num_enabled_connections(), 4);
+        assert_eq!(genome.num_disabled_connections(), 2);
+        assert_eq!(genome.max_depth(), 3);
+    }
+
+    #[test]
+    fn output_activation_functions() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            output_activation_functions: Some(vec![ActivationFunction::Sigmoid, ActivationFunction::Tanh]),
+            ..NeatConfig::default()
+        };
+        let genome = Genome::with_config(2, 2, &mut innovation_record, &config, &mut rand::thread_rng());
+        let activations: Vec<ActivationFunction> = genome
+            .node
+            .iter()
+            .filter(|node| node.node_type == NodeType::Output)
+            .map(|node| node.activation)
+            .collect();
+        assert_eq!(activations, vec![ActivationFunction::Sigmoid, ActivationFunction::Tanh]);
+
+        // A single function applies to every output
+        let config = NeatConfig {
+            output_activation_function: ActivationFunction::ReLU,
+            ..NeatConfig::default()
+        };
+        let genome = Genome::with_config(2, 2, &mut innovation_record, &config, &mut rand::thread_rng());
+        assert!(genome
+            .node
+            .iter()
+            .filter(|node| node.node_type == NodeType::Output)
+            .all(|node| node.activation == ActivationFunction::ReLU));
+    }
+
+    #[test]
+    fn average_weights_crossover() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            crossover_mode: CrossoverMode::AverageWeights,
+            ..NeatConfig::default()
+        };
+        let mut parent_1 = Genome::new(2, 1, &mut innovation_record);
+        let mut parent_2 = parent_1.clone();
+        parent_1.genes.iter_mut().for_each(|gene| gene.weight = 0.2);
+        parent_2.genes.iter_mut().for_each(|gene| gene.weight = 0.8);
+        // Excess gene only the fitter parent has
+        parent_1.add_node(&mut innovation_record, &config, &mut rand::thread_rng());
+
+        let child = parent_1.crossover(parent_2, &config, &mut rand::thread_rng());
+        assert_eq!(child.genes.len(), parent_1.genes.len());
+        for (child_gene, parent_gene) in child.genes.iter().zip(&parent_1.genes) {
+            if parent_gene.innovation < 3 {
+                assert!((child_gene.weight - 0.5).abs() < 1e-12);
+            } else {
+                assert_eq!(child_gene.weight, parent_gene.weight);
+            }
+        }
+    }
+
+    #[test]
+    fn distance_without_matching_genes() {
+        let mut innovation_record = InnovationRecord::new();
+        let genome = Genome::new(2, 1, &mut innovation_record);
+        let mut other = genome.clone();
+        other.genes.iter_mut().for_each(|gene| gene.innovation += 10);
+
+        assert_eq!(genome.compatability_distance(&other, &NeatConfig::default()), 2.0);
+    }
+
+    #[test]
+    fn genome_diff() {
+        let mut genome = Genome::new_blank(3, 1, 2);
+        let mut other = Genome::new_blank(3, 1, 2);
+        for (innovation, weight) in [(0, 0.5), (1, -1.0), (3, 2.0), (6, 1.5), (7, 0.0)] {
+            genome.genes.push(ConnectionGene::new(0, 3, weight, innovation));
+        }
+        for (innovation, weight) in [(0, 0.25), (1, -1.0), (2, 1.0), (4, 0.5)] {
+            other.genes.push(ConnectionGene::new(0, 3, weight, innovation));
+        }
+
+        let diff = genome.diff(&other);
+        assert_eq!(diff.matching, vec![0, 1]);
+        assert_eq!(diff.weight_deltas, vec![0.25, 0.0]);
+        assert_eq!(diff.disjoint, vec![2, 3, 4]);
+        assert_eq!(diff.excess, vec![6, 7]);
+        assert_eq!(genome.compatability_distance(&other, &NeatConfig::default()), 5.0 / 5.0 + 0.4 * 0.125);
+
+        let reverse = other.diff(&genome);
+        assert_eq!(reverse.weight_deltas, vec![-0.25, 0.0]);
+        assert_eq!(reverse.disjoint, diff.disjoint);
+        assert_eq!(reverse.excess, diff.excess);
+
+        // Weights span -1..2, so each bin is 0.75 wide
+        assert_eq!(genome.weight_histogram(4), vec![1, 1, 1, 2]);
+        assert_eq!(genome.weight_histogram(1), vec![5]);
+        assert!(genome.weight_histogram(0).is_empty());
+    }
+
+    #[test]
+    fn activation_compatibility() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig::default();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        for _ in 0..4 {
+            genome.add_node(&mut innovation_record, &config, &mut rand::thread_rng());
+        }
+        let mut other = genome.clone();
+        other
+            .node
+            .iter_mut()
+            .filter(|node| node.node_type == NodeType::Hidden)
+            .for_each(|node| node.activation = ActivationFunction::Tanh);
+
+        assert_eq!(genome.compatability_distance(&other, &config), 0.0);
+        let config = NeatConfig {
+            compatibility_activation_coefficient: 1.0,
+            ..NeatConfig::default()
+        };
+        // Four of the eight nodes differ
+        assert_eq!(genome.compatability_distance(&other, &config), 0.5);
+    }
+
+    #[test]
+    fn mutate_to_relu6() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            activation_functions: vec![ActivationFunction::ReLU6],
+            ..NeatConfig::default()
+        };
+        let mut genome = Genome::from_spec(1, 1, 1, &[(0, 3, 1.0, true), (3, 2, 1.0, true)], &mut innovation_record);
+        genome.node[2].activation = ActivationFunction::None;
+        genome.mutate_activation(&config, &mut rand::thread_rng());
+        assert_eq!(genome.node[3].activation, ActivationFunction::ReLU6);
+        assert_eq!(genome.feed_forward(vec![10.0], &config), vec![6.0]);
+    }
+
+    #[test]
+    fn custom_activation() {
+        let mut innovation_record = InnovationRecord::new();
+        let mut config = NeatConfig {
+            output_activation_function: ActivationFunction::None,
+            weight_mutation_prob: 0.0,
+            new_node_prob: 0.0,
+            new_connection_prob: 0.0,
+            activation_mutation_prob: 1.0,
+            activation_functions: vec![],
+            ..NeatConfig::default()
+        };
+        let square = config.register_activation(|x| x * x);
+        assert_eq!(config.activation_functions, vec![square]);
+        assert!(config.validate().is_ok());
+
+        // input -> hidden -> output
+        let mut genome = Genome::with_config(1, 1, &mut innovation_record, &config, &mut rand::thread_rng());
+        genome.genes.clear();
+        let hidden_id = innovation_record.new_node_innovation();
+        genome.node.push(NodeGene::new(hidden_id, NodeType::Hidden, 2, 0.0, 0.0));
+        genome.genes.push(ConnectionGene::new(0, hidden_id, 1.0, innovation_record.new_innovation(0, hidden_id)));
+        genome.genes.push(ConnectionGene::new(hidden_id, 2, 1.0, innovation_record.new_innovation(hidden_id, 2)));
+        genome.recalculate_layers();
+
+        genome.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
+        assert_eq!(get_node(hidden_id, &genome.node).unwrap().activation, square);
+        assert_eq!(genome.feed_forward(vec![3.0], &config), vec![9.0]);
+        assert_eq!(genome.compile(&config)(&[-2.0]), vec![4.0]);
+    }
+
+    #[test]
+    fn distance_ignoring_disabled() {
+        let mut innovation_record = InnovationRecord::new();
+        let genome = Genome::new(2, 1, &mut innovation_record);
+        let mut other = genome.clone();
+        other.genes[0].enabled = false;
+        other.genes[0].weight += 1.0;
+
+        let config = NeatConfig::default();
+        assert!(genome.compatability_distance(&other, &config) > 0.0);
+        let config = NeatConfig {
+            compatibility_ignore_disabled: true,
+            ..NeatConfig::default()
+        };
+        assert_eq!(genome.compatability_distance(&other, &config), 0.0);
+    }
+
+    #[test]
+    fn compatibility_normalization_threshold() {
+        let mut innovation_record = InnovationRecord::new();
+        let connections = [(0, 3, 1.0, true), (1, 3, 1.0, true), (2, 3, 1.0, true)];
+        let genome = Genome::from_spec(2, 1, 0, &connections, &mut innovation_record);
+        let other = Genome::from_spec(2, 1, 0, &connections[..2], &mut innovation_record);
+        let distance = |threshold: usize| {
+            let config = NeatConfig {
+                compatibility_normalization_threshold: threshold,
+                ..NeatConfig::default()
+            };
+            genome.compatability_distance(&other, &config)
+        };
+
+        // One excess gene, divided by the 3 connections of the larger genome once it is big enough
+        assert_eq!(distance(0), 1.0 / 3.0);
+        assert_eq!(distance(3), 1.0 / 3.0);
+        assert_eq!(distance(4), 1.0);
+    }
+
+    #[test]
+    fn compatibility_breakdown() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            compatibility_activation_coefficient: 0.5,
+            ..NeatConfig::default()
+        };
+        let mut genome = Genome::new(3, 2, &mut innovation_record);
+        let mut other = genome.clone();
+        for _ in 0..10 {
+            genome.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
+            other.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
+        }
+        genome.mutate_activation(&config, &mut rand::thread_rng());
+
+        let breakdown = genome.compatibility_breakdown(&other, &config);
+        let diff = genome.diff(&other);
+        assert_eq!(breakdown.num_disjoint, diff.disjoint.len());
+        assert_eq!(breakdown.num_excess, diff.excess.len());
+        assert_eq!(breakdown.num_matching, diff.matching.len());
+        let reconstructed = (breakdown.num_disjoint + breakdown.num_excess) as f64 / breakdown.normalizer
+            + 0.4 * breakdown.avg_weight_diff
+            + 0.5 * breakdown.activation_difference;
+        assert!((breakdown.total_distance - reconstructed).abs() < 1e-12);
+        assert_eq!(breakdown.total_distance, genome.compatability_distance(&other, &config));
+    }
+
+    #[test]
+    fn active_paths() {
+        let mut innovation_record = InnovationRecord::new();
+        let connections = [
+            (0, 4, 1.0, true),
+            (1, 4, 1.0, true),
+            (4, 3, 1.0, true),
+            (0, 3, 1.0, true),
+            (1, 3, 1.0, false),
+            (2, 3, 1.0, true),
+        ];
+        let genome = Genome::from_spec(2, 1, 1, &connections, &mut innovation_record);
+
+        let mut paths = genome.active_paths(usize::MAX);
+        paths.sort();
+        assert_eq!(paths, vec![vec![0, 3], vec![0, 4, 3], vec![1, 4, 3]]);
+        assert_eq!(genome.active_paths(2).len(), 2);
+    }
+
+    #[test]
+    fn topological_order() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig::default();
+        let mut genome = Genome::new(3, 2, &mut innovation_record);
+        for _ in 0..30 {
+            genome.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
+        }
+
+        let order = genome.topological_order().unwrap();
+        assert_eq!(order.len(), genome.node.len());
+        let position = |id: usize| order.iter().position(|&node| node == id).unwrap();
+        for gene in genome.genes.iter().filter(|gene| gene.enabled) {
+            assert!(position(gene.in_node) < position(gene.out_node));
+        }
+
+        // Connecting the output back to an input closes a loop
+        genome.genes.push(ConnectionGene::new(4, 0, 1.0, innovation_record.new_innovation(4, 0)));
+        assert_eq!(genome.topological_order(), Err(GenomeError::CycleDetected));
+    }
+
+    #[test]
+    fn connection_surgery() {
+        let mut innovation_record = InnovationRecord::new();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        for innovation in 0..3 {
+            assert!(genome.set_weight(innovation, 0.5));
+        }
+        assert_eq!(genome.weight(1), Some(0.5));
+        assert_eq!(genome.weight(3), None);
+        assert!(!genome.set_weight(3, 1.0));
+        let before = genome.feed_forward(vec![1.0, 0.0], &NeatConfig::default());
+
+        // Innovation 0 connects the first input to the output
+        genome.set_weight(0, -2.0);
+        assert_eq!(genome.weight(0), Some(-2.0));
+        let after = genome.feed_forward(vec![1.0, 0.0], &NeatConfig::default());
+        assert!(after[0] < before[0]);
+
+        // Without the connection the first input has no effect
+        assert!(genome.toggle_connection(0));
+        assert!(!genome.genes[0].enabled);
+        let config = NeatConfig::default();
+        assert_eq!(genome.feed_forward(vec![1.0, 0.0], &config), genome.feed_forward(vec![0.0, 0.0], &config));
+        assert!(genome.toggle_connection(0));
+        assert_eq!(genome.feed_forward(vec![1.0, 0.0], &NeatConfig::default()), after);
+        assert!(!genome.toggle_connection(3));
+    }
+
+    #[test]
+    fn bias_modes() {
+        let mut innovation_record = InnovationRecord::new();
+        for (use_bias, bias_to_hidden_only) in [(false, false), (true, true)] {
+            let config = NeatConfig {
+                use_bias,
+                bias_to_hidden_only,
+                ..NeatConfig::default()
+            };
+            let mut genome = Genome::with_config(2, 1, &mut innovation_record, &config, &mut rand::thread_rng());
+            assert_eq!(genome.node.len(), 4);
+            assert_eq!(genome.genes.len(), 2);
+            for _ in 0..40 {
+                genome.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
+            }
+
+            let bias_genes: Vec<&ConnectionGene> =
+                genome.genes.iter().filter(|gene| gene.in_node == genome.bias_node).collect();
+            if use_bias {
+                assert!(bias_genes.iter().all(|gene| {
+                    get_node(gene.out_node, &genome.node).unwrap().node_type == NodeType::Hidden
+                }));
+            } else {
+                assert!(bias_genes.is_empty());
+            }
+            let outputs = genome.feed_forward(vec![0.5, -0.5], &config);
+            assert_eq!(outputs, genome.compile(&config)(&[0.5, -0.5]));
+        }
+    }
+
+    #[test]
+    fn no_bias_outputs() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            no_bias_outputs: vec![1],
+            new_connection_prob: 1.0,
+            ..NeatConfig::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut genome = Genome::with_config(2, 2, &mut innovation_record, &config, &mut rng);
+        let bias_targets = |genome: &Genome| -> Vec<usize> {
+            genome
+                .genes
+                .iter()
+                .filter(|gene| gene.in_node == genome.bias_node)
+                .map(|gene| gene.out_node)
+                .collect()
+        };
+        assert_eq!(bias_targets(&genome), vec![3]);
+
+        for _ in 0..50 {
+            genome.mutate(&mut innovation_record, &config, &mut rng);
+        }
+        assert!(!bias_targets(&genome).contains(&4));
+
+        assert!(genome.remove_bias_connection(3));
+        assert!(!genome.remove_bias_connection(3));
+        assert!(!bias_targets(&genome).contains(&3));
+    }
+
+    #[test]
+    fn output_clamp() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            output_activation_function: ActivationFunction::ReLU,
+            output_clamp: Some((0.0, 1.0)),
+            ..NeatConfig::default()
+        };
+        let mut rng = rand::thread_rng();
+        let mut genome = Genome::with_config(3, 2, &mut innovation_record, &config, &mut rng);
+        for _ in 0..20 {
+            genome.mutate(&mut innovation_record, &config, &mut rng);
+        }
+        let network = genome.compile(&config);
+        for _ in 0..100 {
+            let inputs: Vec<f64> = (0..3).map(|_| rng.gen_range(-100.0..100.0)).collect();
+            for output in genome.feed_forward(inputs.clone(), &config).into_iter().chain(network(&inputs)) {
+                assert!((0.0..=1.0).contains(&output));
+            }
+        }
+
+        // The clamp is read from the config the network is evaluated with
+        let config = NeatConfig {
+            output_clamp: Some((0.0, 0.0)),
+            ..config
+        };
+        assert_eq!(genome.feed_forward(vec![1.0, 2.0, 3.0], &config), vec![0.0, 0.0]);
+        assert_eq!(genome.compile(&config)(&[1.0, 2.0, 3.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn prune_weak_connections() {
+        let mut innovation_record = InnovationRecord::new();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        genome.set_weight(0, 0.001);
+        genome.set_weight(1, 0.5);
+        genome.set_weight(2, 0.5);
+        let before = genome.feed_forward(vec![1.0, 1.0], &NeatConfig::default());
+
+        genome.prune_weak_connections(0.01);
+        assert!(!genome.genes[0].enabled);
+        assert!(genome.genes[1].enabled && genome.genes[2].enabled);
+        assert_ne!(genome.feed_forward(vec![1.0, 1.0], &NeatConfig::default()), before);
+    }
+
+    #[test]
+    fn frozen_connections() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            max_nodes: Some(10),
+            ..NeatConfig::default()
+        };
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        genome.set_weight(0, 0.25);
+        assert!(genome.freeze_connection(0));
+        assert!(!genome.freeze_connection(100));
+        // A frozen disabled connection isn't re-enabled by adding connections
+        genome.set_weight(1, 1.0);
+        genome.toggle_connection(1);
+        assert!(genome.freeze_connection(1));
+        let other_weight = genome.weight(2);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..2000 {
+            genome.mutate(&mut innovation_record, &config, &mut rng);
+            genome.prune_weak_connections(0.5);
+        }
+        assert_eq!(genome.weight(0), Some(0.25));
+        assert!(genome.genes[0].enabled);
+        assert!(!genome.genes[1].enabled);
+        assert_ne!(genome.weight(2), other_weight);
+
+        genome.unfreeze_connection(0);
+        genome.prune_weak_connections(0.5);
+        assert!(!genome.genes[0].enabled);
+    }
+
+    #[test]
+    fn validate() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig::default();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        for _ in 0..20 {
+            genome.mutate(&mut innovation_record, &config, &mut rand::thread_rng());
+        }
+        assert_eq!(genome.validate(), Ok(()));
+
+        let mut broken = genome.clone();
+        broken.node.retain(|node| node.id != 3);
+        assert_eq!(broken.validate(), Err(GenomeError::MissingNode(3)));
+
+        let mut broken = genome.clone();
+        broken.genes.push(ConnectionGene::new(0, 100, 1.0, 50));
+        assert_eq!(broken.validate(), Err(GenomeError::DanglingConnection(50)));
+
+        let mut broken = genome.clone();
+        broken.genes.push(ConnectionGene::new(3, 1, 1.0, 51));
+        assert_eq!(broken.validate(), Err(GenomeError::ConnectionIntoInput(51)));
+
+        let mut broken = genome.clone();
+        let duplicate = ConnectionGene::new(broken.genes[0].in_node, broken.genes[0].out_node, 1.0, 52);
+        broken.genes.push(duplicate);
+        assert_eq!(
+            broken.validate(),
+            Err(GenomeError::DuplicateConnection(duplicate.in_node, duplicate.out_node))
+        );
+    }
+
+    #[test]
+    fn exhaustive_connection_search() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            exhaustive_connection_max_nodes: 10,
+            ..NeatConfig::default()
+        };
+        // Splitting input 0 -> output leaves only the links from input 1 and the bias to the new
+        // node to add
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        genome.genes.retain(|gene| gene.in_node == 0);
+        genome.add_node(&mut innovation_record, &config, &mut rand::thread_rng());
+        genome.genes.push(ConnectionGene::new(1, 3, 1.0, innovation_record.new_innovation(1, 3)));
+        genome.genes.push(ConnectionGene::new(2, 3, 1.0, innovation_record.new_innovation(2, 3)));
+        genome.genes[0].enabled = true;
+        let hidden_id = genome.node[4].id;
+
+        for _ in 0..2 {
+            genome.add_connection(&mut innovation_record, &config, &mut rand::thread_rng());
+        }
+        assert!(genome.genes.iter().any(|gene| gene.in_node == 1 && gene.out_node == hidden_id));
+        assert!(genome.genes.iter().any(|gene| gene.in_node == 2 && gene.out_node == hidden_id));
+        assert_eq!(genome.validate(), Ok(()));
+
+        // Fully connected, nothing left to add
+        let genes = genome.genes.len();
+        genome.add_connection(&mut innovation_record, &config, &mut rand::thread_rng());
+        assert_eq!(genome.genes.len(), genes);
+    }
+
+    #[test]
+    fn sampled_connection_search() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            connection_mutation_attempts: 1000,
+            ..NeatConfig::default()
+        };
+        // 300 hidden nodes each between input 0 and the output
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        for _ in 0..300 {
+            let id = innovation_record.new_node_innovation();
+            genome.node.push(NodeGene::new(id, NodeType::Hidden, 2, 0.0, 0.0));
+            genome.genes.push(ConnectionGene::new(0, id, 1.0, innovation_record.new_innovation(0, id)));
+            genome.genes.push(ConnectionGene::new(id, 3, 1.0, innovation_record.new_innovation(id, 3)));
+        }
+        genome.recalculate_layers();
+
+        // Few of the sampled pairs are new connections, so the outcome is pinned with a seed
+        let genes = genome.genes.len();
+        genome.add_connection(&mut innovation_record, &config, &mut StdRng::seed_from_u64(1));
+        assert_eq!(genome.genes.len(), genes + 1);
+        assert_eq!(genome.validate(), Ok(()));
+    }
+
+    #[test]
+    fn structural_hash() {
+        let mut innovation_record = InnovationRecord::new();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        genome.add_node(&mut innovation_record, &NeatConfig::default(), &mut rand::thread_rng());
+        let mut shuffled = genome.clone();
+        shuffled.genes.reverse();
+        shuffled.fitness = 10.0;
+        assert_eq!(genome.structural_hash(), shuffled.structural_hash());
+
+        // Only enabled connections count, so change one the split left enabled
+        let enabled = genome.genes.iter().position(|gene| gene.enabled).unwrap();
+        let weight = genome.genes[enabled].weight;
+        genome.genes[enabled].weight = weight + 0.5;
+        assert_ne!(genome.structural_hash(), shuffled.structural_hash());
+
+        genome.genes[enabled].weight = weight;
+        genome.node[4].activation = ActivationFunction::Tanh;
+        assert_ne!(genome.structural_hash(), shuffled.structural_hash());
+    }
+
+    #[test]
+    fn from_spec() {
+        let mut innovation_record = InnovationRecord::new();
+        let connections = [
+            (0, 4, 1.0, true),
+            (1, 4, 1.0, true),
+            (4, 3, 2.0, true),
+            (2, 3, -1.0, true),
+            (0, 3, 5.0, false),
+        ];
+        let mut genome = Genome::from_spec(2, 1, 1, &connections, &mut innovation_record);
+        assert_eq!(genome.validate(), Ok(()));
+        assert_eq!(genome.num_hidden_nodes(), 1);
+
+        let sigmoid = |x: f64| 1.0 / (1.0 + (-4.9 * x).exp());
+        let output = genome.feed_forward(vec![1.0, 0.0], &NeatConfig::default());
+        assert_eq!(output, vec![sigmoid(2.0 * sigmoid(1.0) - 1.0)]);
+
+        // Rebuilding the same spec reuses the innovations
+        let other = Genome::from_spec(2, 1, 1, &connections, &mut innovation_record);
+        assert_eq!(genome.compatability_distance(&other, &NeatConfig::default()), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "network expects 2 inputs, got 3")]
+    fn input_size_mismatch() {
+        let mut innovation_record = InnovationRecord::new();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        genome.feed_forward(vec![1.0, 0.0, 1.0], &NeatConfig::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "network expects 2 inputs, got 1")]
+    fn compiled_input_size_mismatch() {
+        let mut innovation_record = InnovationRecord::new();
+        let genome = Genome::new(2, 1, &mut innovation_record);
+        genome.compile(&NeatConfig::default())(&[1.0]);
+    }
+
+    #[test]
+    fn canonicalize() {
+        let mut innovation_record = InnovationRecord::new();
+        let genome = Genome::from_spec(
+            2,
+            1,
+            2,
+            &[(0, 4, 1.0, true), (1, 5, 2.0, true), (4, 3, 0.5, true), (5, 3, -0.5, true)],
+            &mut innovation_record,
+        );
+        // The same network with the hidden nodes numbered the other way round
+        let mut renumbered = Genome::from_spec(
+            2,
+            1,
+            2,
+            &[(5, 3, 0.5, true), (1, 4, 2.0, true), (4, 3, -0.5, true), (0, 5, 1.0, true)],
+            &mut innovation_record,
+        );
+        assert_ne!(genome.structural_hash(), renumbered.structural_hash());
+
+        let mut canonical = genome.canonicalize();
+        let canonical_renumbered = renumbered.canonicalize();
+        let connections = |genome: &Genome| -> Vec<(usize, usize, usize, f64)> {
+            genome
+                .genes
+                .iter()
+                .map(|gene| (gene.innovation, gene.in_node, gene.out_node, gene.weight))
+                .collect()
+        };
+        assert_eq!(connections(&canonical), connections(&canonical_renumbered));
+        assert_eq!(canonical.structural_hash(), canonical_renumbered.structural_hash());
+        assert_eq!(canonical.validate(), Ok(()));
+
+        for inputs in [vec![0.0, 1.0], vec![1.0, 0.5]] {
+            let config = NeatConfig::default();
+            assert_eq!(canonical.feed_forward(inputs.clone(), &config), renumbered.feed_forward(inputs, &config));
+        }
+    }
+
+    #[test]
+    fn crossover_node_parameters() {
+        let mut innovation_record = InnovationRecord::new();
+        let connections = [(0, 3, 1.0, true), (3, 2, 1.0, true)];
+        let mut fitter = Genome::from_spec(1, 1, 1, &connections, &mut innovation_record);
+        fitter.node[3].activation = ActivationFunction::Tanh;
+        let mut other = fitter.clone();
+        other.node[3].activation = ActivationFunction::ReLU;
+
+        let activations = |config: &NeatConfig| -> HashSet<ActivationFunction> {
+            let mut rng = StdRng::seed_from_u64(0);
+            (0..50)
+                .map(|_| {
+                    let child = fitter.clone().crossover(other.clone(), config, &mut rng);
+                    child.node[3].activation
+                })
+                .collect()
+        };
+        assert_eq!(activations(&NeatConfig::default()), HashSet::from([ActivationFunction::Tanh]));
+        let config = NeatConfig {
+            crossover_node_parameters: true,
+            ..NeatConfig::default()
+        };
+        assert_eq!(activations(&config), HashSet::from([ActivationFunction::Tanh, ActivationFunction::ReLU]));
+    }
+
+    #[test]
+    fn multi_crossover() {
+        let mut innovation_record = InnovationRecord::new();
+        let mut parent = |fitness: f64, connections: &[(usize, usize, f64, bool)]| {
+            let mut genome = Genome::from_spec(2, 1, 2, connections, &mut innovation_record);
+            genome.fitness = fitness;
+            genome
+        };
+        let second = parent(2.0, &[(0, 3, 4.0, true), (2, 3, 1.0, true), (0, 4, 1.0, true), (4, 5, 1.0, true)]);
+        let first = parent(3.0, &[(0, 3, 1.0, true), (1, 3, 2.0, true), (5, 4, 1.0, false)]);
+        let third = parent(1.0, &[(0, 3, 7.0, true), (1, 3, 5.0, true)]);
+
+        let config = NeatConfig {
+            crossover_mode: CrossoverMode::AverageWeights,
+            ..NeatConfig::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let child = Genome::multi_crossover(&[&second, &third, &first], &config, &mut rng);
+        let weight = |child: &Genome, in_node: usize, out_node: usize| {
+            child
+                .genes
+                .iter()
+                .find(|gene| gene.in_node == in_node && gene.out_node == out_node)
+                .map(|gene| gene.weight)
+        };
+        assert_eq!(weight(&child, 0, 3), Some(4.0));
+        assert_eq!(weight(&child, 1, 3), Some(3.5));
+        assert_eq!(weight(&child, 2, 3), Some(1.0));
+        assert_eq!(weight(&child, 0, 4), Some(1.0));
+        // 4 -> 5 would close a loop with the fittest parent's 5 -> 4
+        assert_eq!(weight(&child, 4, 5), None);
+        assert_eq!(child.validate(), Ok(()));
+
+        // Shared genes come whole from one of the parents that have them
+        let config = NeatConfig {
+            crossover_mode: CrossoverMode::RandomParent,
+            ..NeatConfig::default()
+        };
+        let mut seen = HashSet::new();
+        for _ in 0..50 {
+            let child = Genome::multi_crossover(&[&second, &third, &first], &config, &mut rng);
+            let weight = weight(&child, 0, 3).unwrap();
+            assert!([1.0, 4.0, 7.0].contains(&weight));
+            seen.insert(weight as i64);
+        }
+        assert_eq!(seen.len(), 3);
+
+        // On equal fitness the smaller parent leads, so its 5 -> 4 wins over 4 -> 5
+        let mut first = first;
+        first.fitness = 2.0;
+        let config = NeatConfig {
+            crossover_equal_fitness_prefer_smaller: true,
+            ..NeatConfig::default()
+        };
+        let child = Genome::multi_crossover(&[&second, &first], &config, &mut rng);
+        assert_eq!(weight(&child, 4, 5), None);
+        assert!(weight(&child, 5, 4).is_some());
+    }
+
+    #[test]
+    fn multi_crossover_node_parameters() {
+        let mut innovation_record = InnovationRecord::new();
+        let connections = [(0, 4, 1.0, true), (4, 3, 1.0, true)];
+        let mut parents: Vec<Genome> = [ActivationFunction::Tanh, ActivationFunction::ReLU, ActivationFunction::Swish]
+            .iter()
+            .enumerate()
+            .map(|(i, &activation)| {
+                let mut genome = Genome::from_spec(2, 1, 1, &connections, &mut innovation_record);
+                genome.fitness = i as f64;
+                genome.node[4].activation = activation;
+                genome
+            })
+            .collect();
+        parents.reverse();
+        let parents: Vec<&Genome> = parents.iter().collect();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let activations = |config: &NeatConfig, rng: &mut StdRng| -> HashSet<ActivationFunction> {
+            (0..50)
+                .map(|_| Genome::multi_crossover(&parents, config, rng).node[4].activation)
+                .collect()
+        };
+        assert_eq!(activations(&NeatConfig::default(), &mut rng), HashSet::from([ActivationFunction::Swish]));
+        let config = NeatConfig {
+            crossover_node_parameters: true,
+            ..NeatConfig::default()
+        };
+        assert_eq!(activations(&config, &mut rng).len(), 3);
+    }
+
+    #[test]
+    fn output_order() {
+        let mut innovation_record = InnovationRecord::new();
+        // Each output passes the bias on with its own weight: output i gives i + 1
+        let connections = [(1, 2, 1.0, true), (1, 3, 2.0, true), (1, 4, 3.0, true)];
+        let mut genome = Genome::from_spec(1, 3, 0, &connections, &mut innovation_record);
+        for node in &mut genome.node {
+            node.activation = ActivationFunction::None;
+        }
+        // Output node order in the genome doesn't change the output order
+        genome.node[2..].reverse();
+
+        let network = genome.compile(&NeatConfig::default());
+        for i in 0..100 {
+            let input = i as f64;
+            assert_eq!(genome.feed_forward(vec![input], &NeatConfig::default()), vec![1.0, 2.0, 3.0]);
+            assert_eq!(network(&[input]), vec![1.0, 2.0, 3.0]);
+        }
+    }
+
+    #[test]
+    fn normal_weight_init() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            weight_init: WeightInit::Normal { mean: 0.0, std: 0.1 },
+            ..NeatConfig::default()
+        };
+        let genome = Genome::with_config(50, 40, &mut innovation_record, &config, &mut StdRng::seed_from_u64(0));
+        let weights: Vec<f64> = genome.genes.iter().map(|gene| gene.weight).collect();
+        assert_eq!(weights.len(), 51 * 40);
+
+        let mean = weights.iter().sum::<f64>() / weights.len() as f64;
+        let std = (weights.iter().map(|weight| (weight - mean).powi(2)).sum::<f64>() / weights.len() as f64).sqrt();
+        assert!(mean.abs() < 0.01);
+        assert!((std - 0.1).abs() < 0.01);
+        // Almost all of a normal distribution is within 4 standard deviations
+        assert!(weights.iter().all(|weight| weight.abs() < 0.4));
+    }
+
+    #[test]
+    fn bias_excluded_from_inputs() {
+        let mut innovation_record = InnovationRecord::new();
+        let genome = Genome::new(3, 2, &mut innovation_record);
+        assert_eq!(genome.num_inputs(), 3);
+        assert_eq!(genome.node.iter().filter(|node| node.node_type == NodeType::Input).count(), 3);
+        // The printed genome labels the bias node apart from the inputs
+        let printed = genome.to_string();
+        assert_eq!(printed.matches("node_type: Bias").count(), 1);
+        assert_eq!(printed.matches("node_type: Input").count(), 3);
+    }
+
+    #[test]
+    fn diff_matches_pairwise_search() {
+        // Looks every gene up in the other genome instead of walking both in order
+        fn pairwise_diff(genes: &[ConnectionGene], other_genes: &[ConnectionGene]) -> GenomeDiff {
+            let max = |genes: &[ConnectionGene]| genes.iter().map(|gene| gene.innovation).max();
+            let mut diff = GenomeDiff::default();
+            let mut matching = vec![];
+            for (genes, other_genes, swapped) in [(genes, other_genes, false), (other_genes, genes, true)] {
+                for gene in genes {
+                    match other_genes.iter().find(|other| other.innovation == gene.innovation) {
+                        Some(other) if !swapped => matching.push((gene.innovation, gene.weight - other.weight)),
+                        Some(_) => {}
+                        None if Some(gene.innovation) > max(other_genes) => diff.excess.push(gene.innovation),
+                        None => diff.disjoint.push(gene.innovation),
+                    }
+                }
+            }
+            matching.sort_by_key(|&(innovation, _)| innovation);
+            (diff.matching, diff.weight_deltas) = matching.into_iter().unzip();
+            diff.disjoint.sort();
+            diff.excess.sort();
+            diff
+        }
+
+        let config = NeatConfig::default();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut innovation_record = InnovationRecord::new();
+        let ancestor = Genome::new(3, 2, &mut innovation_record);
+        for _ in 0..20 {
+            let mut genome = ancestor.clone();
+            let mut other = ancestor.clone();
+            for _ in 0..rng.gen_range(0..15) {
+                genome.mutate(&mut innovation_record, &config, &mut rng);
+            }
+            for _ in 0..rng.gen_range(0..15) {
+                other.mutate(&mut innovation_record, &config, &mut rng);
+            }
+            genome.genes.shuffle(&mut rng);
+
+            let expected = pairwise_diff(&genome.genes, &other.genes);
+            assert_eq!(genome.diff(&other), expected);
+            let weight_diff = if expected.matching.is_empty() {
+                0.0
+            } else {
+                expected.weight_deltas.iter().map(|delta| delta.abs()).sum::<f64>() / expected.matching.len() as f64
+            };
+            let n = genome.genes.len().max(other.genes.len()) as f64;
+            let distance = (expected.disjoint.len() + expected.excess.len()) as f64 / n + 0.4 * weight_diff;
+            assert!((genome.compatability_distance(&other, &config) - distance).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn per_connection_weight_mutation() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            per_connection_weight_mutation: true,
+            weight_mutation_prob: 0.3,
+            new_node_prob: 0.0,
+            new_connection_prob: 0.0,
+            ..NeatConfig::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut genome = Genome::with_config(30, 30, &mut innovation_record, &config, &mut rng);
+        let before = genome.genes.clone();
+        genome.mutate(&mut innovation_record, &config, &mut rng);
+
+        let changed = before.iter().zip(&genome.genes).filter(|(a, b)| a.weight != b.weight).count();
+        let fraction = changed as f64 / before.len() as f64;
+        assert!((fraction - 0.3).abs() < 0.05, "{} of the connections changed", fraction);
+    }
+
     #[test]
     fn compare_check() {
         // Simple comparison of genomes to make sure that sorting by fitness will work
@@ -1,9 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+// Shared history of structural innovations, cloning or passing a record between populations lets
+// them assign identical innovation numbers to identical connections
+#[derive(Clone, Debug, Default)]
 pub struct InnovationRecord {
     // Innovation number stored as a hashmap of (from, to) -> innovation
     pub innovation_number: HashMap<(usize, usize), usize>,
     pub num_nodes: usize,
+    // Next innovation number handed out, kept separately so compacting never reuses one
+    next_innovation: usize,
 }
 
 impl InnovationRecord {
@@ -11,6 +16,7 @@ impl InnovationRecord {
         InnovationRecord {
             innovation_number: HashMap::new(),
             num_nodes: 0,
+            next_innovation: 0,
         }
     }
 
@@ -25,7 +31,8 @@ impl InnovationRecord {
         match innovation {
             Some(innovation) => *innovation,
             None => {
-                let innovation = self.innovation_number.len();
+                let innovation = self.next_innovation;
+                self.next_innovation += 1;
                 self.innovation_number.insert((from, to), innovation);
                 innovation
             }
@@ -37,4 +44,34 @@ impl InnovationRecord {
         self.num_nodes += 1;
         innovation
     }
+
+    // Forgets connections that no longer exist anywhere, a connection that reappears later gets a
+    // new innovation number
+    pub fn compact(&mut self, live_connections: &HashSet<(usize, usize)>) {
+        self.innovation_number
+            .retain(|connection, _| live_connections.contains(connection));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact() {
+        let mut innovation_record = InnovationRecord::new();
+        for i in 0..10 {
+            innovation_record.new_innovation(i, i + 1);
+        }
+        let live: HashSet<(usize, usize)> = [(2, 3), (7, 8)].into_iter().collect();
+        innovation_record.compact(&live);
+
+        assert_eq!(innovation_record.innovation_number.len(), 2);
+        assert_eq!(innovation_record.new_innovation(2, 3), 2);
+        assert_eq!(innovation_record.new_innovation(7, 8), 7);
+        assert!(!innovation_record.has_innovation(0, 1));
+        // New and rediscovered connections don't reuse old numbers
+        assert_eq!(innovation_record.new_innovation(0, 1), 10);
+        assert_eq!(innovation_record.new_innovation(20, 21), 11);
+    }
 }
@@ -1,6 +1,11 @@
+pub mod config;
+pub mod environment;
+pub mod eval;
 pub mod genome;
 pub mod population;
+pub mod runner;
+pub mod innovation_record;
+pub mod normalizer;
+pub mod genes;
 
 mod species;
-mod innovation_record;
-mod genes;
@@ -0,0 +1,103 @@
+// Scales raw network inputs to roughly zero mean and unit variance using per-input statistics.
+// Statistics can be given up front or gathered online with `update` as observations come in
+#[derive(Clone, Debug)]
+pub struct InputNormalizer {
+    count: usize,
+    mean: Vec<f64>,
+    // Sum of squared differences from the mean (Welford's algorithm)
+    m2: Vec<f64>,
+}
+
+impl InputNormalizer {
+    pub fn new(inputs: usize) -> Self {
+        Self {
+            count: 0,
+            mean: vec![0.0; inputs],
+            m2: vec![0.0; inputs],
+        }
+    }
+
+    // Normalizer with known statistics, later updates treat them as a single observation
+    pub fn with_stats(mean: Vec<f64>, std: Vec<f64>) -> Self {
+        assert_eq!(mean.len(), std.len());
+        Self {
+            count: 1,
+            mean,
+            m2: std.iter().map(|std| std * std).collect(),
+        }
+    }
+
+    // Adds an observation to the running statistics
+    pub fn update(&mut self, inputs: &[f64]) {
+        assert_eq!(inputs.len(), self.mean.len());
+        self.count += 1;
+        for (i, &x) in inputs.iter().enumerate() {
+            let delta = x - self.mean[i];
+            self.mean[i] += delta / self.count as f64;
+            self.m2[i] += delta * (x - self.mean[i]);
+        }
+    }
+
+    pub fn mean(&self) -> &[f64] {
+        &self.mean
+    }
+
+    pub fn std(&self) -> Vec<f64> {
+        self.m2
+            .iter()
+            .map(|m2| if self.count > 0 { (m2 / self.count as f64).sqrt() } else { 1.0 })
+            .collect()
+    }
+
+    pub fn normalize(&self, inputs: &[f64]) -> Vec<f64> {
+        assert_eq!(inputs.len(), self.mean.len());
+        inputs
+            .iter()
+            .zip(self.mean.iter().zip(self.std()))
+            .map(|(x, (mean, std))| {
+                // Inputs that never vary are only centered
+                if std > f64::EPSILON {
+                    (x - mean) / std
+                } else {
+                    x - mean
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_stats() {
+        let normalizer = InputNormalizer::with_stats(vec![10.0, -2.0], vec![2.0, 0.5]);
+        assert_eq!(normalizer.normalize(&[12.0, -2.5]), vec![1.0, -1.0]);
+        assert_eq!(normalizer.normalize(&[10.0, -2.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn running_stats() {
+        // Inputs on very different scales
+        let samples: Vec<Vec<f64>> = (0..1000)
+            .map(|i| {
+                let t = i as f64 / 1000.0;
+                vec![500.0 + 200.0 * (t * 40.0).sin(), 0.01 * (t * 13.0).cos() - 3.0]
+            })
+            .collect();
+        let mut normalizer = InputNormalizer::new(2);
+        for sample in &samples {
+            normalizer.update(sample);
+        }
+
+        let normalized: Vec<Vec<f64>> = samples.iter().map(|s| normalizer.normalize(s)).collect();
+        for i in 0..2 {
+            let mean = normalized.iter().map(|n| n[i]).sum::<f64>() / normalized.len() as f64;
+            let variance =
+                normalized.iter().map(|n| (n[i] - mean).powi(2)).sum::<f64>() / normalized.len() as f64;
+            assert!(mean.abs() < 1e-9);
+            assert!((variance - 1.0).abs() < 1e-9);
+        }
+    }
+}
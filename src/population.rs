@@ -1,11 +1,92 @@
-use macroquad::rand::ChooseRandom;
+use crate::config::{NeatConfig, SpeciationMode};
+use crate::environment::{run_episodes, Environment};
+use crate::genes::NodeType;
 use crate::genome::Genome;
 use crate::innovation_record::InnovationRecord;
 use crate::species::Specie;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+// Creation of a species during speciation and the species it split from
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpecieOrigin {
+    pub id: usize,
+    pub parent_id: Option<usize>,
+    pub generation: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PopulationError {
+    EmptyPopulation,
+    NoInputs,
+    NoOutputs,
+    InvalidConfig(String),
+    // Inputs and outputs of the population and of the genome
+    IncompatibleGenome {
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+    // Inputs and outputs of this population and of the one merged into it
+    IncompatiblePopulation {
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+    // The population merged in was evolved with a different config
+    ConfigMismatch,
+}
+
+impl Display for PopulationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PopulationError::EmptyPopulation => write!(f, "population size must be at least 1"),
+            PopulationError::NoInputs => write!(f, "genomes need at least one input"),
+            PopulationError::NoOutputs => write!(f, "genomes need at least one output"),
+            PopulationError::InvalidConfig(reason) => write!(f, "invalid config: {}", reason),
+            PopulationError::IncompatibleGenome { expected, found } => write!(
+                f,
+                "genome has {} inputs and {} outputs, expected {} and {}",
+                found.0, found.1, expected.0, expected.1
+            ),
+            PopulationError::IncompatiblePopulation { expected, found } => write!(
+                f,
+                "population has {} inputs and {} outputs, expected {} and {}",
+                found.0, found.1, expected.0, expected.1
+            ),
+            PopulationError::ConfigMismatch => write!(f, "populations have different configs"),
+        }
+    }
+}
+
+impl Error for PopulationError {}
+
+// Genomes and settings of a population without its random state, to inspect or to continue from
+// with `Population::from_snapshot`
+#[derive(Clone, Debug)]
+pub struct PopulationSnapshot {
+    // Genomes grouped by the id of their species
+    pub species: Vec<(usize, Vec<Genome>)>,
+    pub generation: usize,
+    pub champion: Option<Genome>,
+    pub config: NeatConfig,
+    pub innovation_record: InnovationRecord,
+    pub input_num: usize,
+    pub output_num: usize,
+    pub hidden_num: usize,
+    pub population_size: usize,
+}
 
 pub struct Population {
     pub genomes: Vec<Genome>,
     species: Vec<Specie>,
+    // Species id of each genome, by index, as of the last speciation
+    genome_species: Vec<usize>,
+    next_specie_id: usize,
+    lineage: Vec<SpecieOrigin>,
 
     // Includes bias node
     pub input_num: usize,
@@ -13,37 +94,459 @@ pub struct Population {
     pub output_num: usize,
     pub hidden_num: usize,
     pub population_size: usize,
+    // Size set by `resize` that the next `evolve` culls the current generation to
+    pending_size: Option<usize>,
 
     pub age: usize,
     pub champion: Option<Genome>,
+    // Generations since the champion last improved
+    pub stagnation: usize,
 
     innovation_record: InnovationRecord,
+
+    pub config: NeatConfig,
+    // Structural mutation probabilities set aside while only weights are mutated
+    structural_mutation_probs: Option<(f64, f64)>,
+
+    // Source of all randomness in evolution, seeded from `config.seed` when set
+    rng: StdRng,
+
+    // Fitness of the last evaluated generation by structural hash, when `config.cache_fitness` is set
+    fitness_cache: HashMap<u64, f64>,
 }
 
 impl Population {
-    pub fn new(population_size: usize, inputs: usize, outputs: usize, hidden: usize) -> Self {
+    // Creates a population with a fresh innovation record and the default config
+    pub fn new(
+        population_size: usize,
+        inputs: usize,
+        outputs: usize,
+        hidden: usize,
+    ) -> Result<Self, PopulationError> {
+        Self::create(population_size, inputs, outputs, hidden, NeatConfig::default(), InnovationRecord::new())
+    }
+
+    pub fn with_config(
+        population_size: usize,
+        inputs: usize,
+        outputs: usize,
+        hidden: usize,
+        config: NeatConfig,
+    ) -> Result<Self, PopulationError> {
+        Self::create(population_size, inputs, outputs, hidden, config, InnovationRecord::new())
+    }
+
+    // Creates a population that continues from an existing innovation record, e.g. one taken from
+    // another population with `innovation_record` so both agree on innovation numbers
+    pub fn with_innovation_record(
+        population_size: usize,
+        inputs: usize,
+        outputs: usize,
+        hidden: usize,
+        innovation_record: InnovationRecord,
+    ) -> Result<Self, PopulationError> {
+        Self::create(population_size, inputs, outputs, hidden, NeatConfig::default(), innovation_record)
+    }
+
+    fn create(
+        population_size: usize,
+        inputs: usize,
+        outputs: usize,
+        hidden: usize,
+        config: NeatConfig,
+        innovation_record: InnovationRecord,
+    ) -> Result<Self, PopulationError> {
+        if population_size == 0 {
+            return Err(PopulationError::EmptyPopulation);
+        }
+        if inputs == 0 {
+            return Err(PopulationError::NoInputs);
+        }
+        if outputs == 0 {
+            return Err(PopulationError::NoOutputs);
+        }
+        config.validate().map_err(PopulationError::InvalidConfig)?;
+        if let Some(functions) = &config.output_activation_functions {
+            if functions.len() != outputs {
+                return Err(PopulationError::InvalidConfig(format!(
+                    "expected {} output activation functions, got {}",
+                    outputs,
+                    functions.len()
+                )));
+            }
+        }
+        if let Some(index) = config.no_bias_outputs.iter().find(|&&index| index >= outputs) {
+            return Err(PopulationError::InvalidConfig(format!(
+                "no_bias_outputs has output index {}, but there are {} outputs",
+                index, outputs
+            )));
+        }
+
         let mut population = Self {
             genomes: vec![],
             species: vec![],
+            genome_species: vec![],
+            next_specie_id: 0,
+            lineage: vec![],
             input_num: inputs,
             output_num: outputs,
             hidden_num: hidden,
             population_size,
+            pending_size: None,
             age: 0,
             champion: None,
-            innovation_record: InnovationRecord::new(),
+            stagnation: 0,
+            innovation_record,
+            rng: match config.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            config,
+            structural_mutation_probs: None,
+            fitness_cache: HashMap::new(),
         };
 
-        let genome = Genome::new(inputs, outputs, &mut population.innovation_record);
+        let genome = Genome::with_config(
+            inputs,
+            outputs,
+            &mut population.innovation_record,
+            &population.config,
+            &mut population.rng,
+        );
         for _ in 0..population_size {
             let mut new_genome = genome.clone();
-            new_genome.mutate(&mut population.innovation_record);
+            new_genome.mutate(&mut population.innovation_record, &population.config, &mut population.rng);
             population.genomes.push(new_genome);
         }
 
+        Ok(population)
+    }
+
+    pub fn snapshot(&self) -> PopulationSnapshot {
+        let mut species: Vec<(usize, Vec<Genome>)> = vec![];
+        for (index, genome) in self.genomes.iter().enumerate() {
+            // Genomes that haven't been speciated yet are grouped under the next species id
+            let specie_id = self.species_of(index).unwrap_or(self.next_specie_id);
+            match species.iter_mut().find(|(id, _)| *id == specie_id) {
+                Some((_, genomes)) => genomes.push(genome.clone()),
+                None => species.push((specie_id, vec![genome.clone()])),
+            }
+        }
+        PopulationSnapshot {
+            species,
+            generation: self.age,
+            champion: self.champion.clone(),
+            config: self.config.clone(),
+            innovation_record: self.innovation_record.clone(),
+            input_num: self.input_num,
+            output_num: self.output_num,
+            hidden_num: self.hidden_num,
+            population_size: self.population_size,
+        }
+    }
+
+    // Rebuilds a population from a snapshot with a new random state seeded from `seed`. Species
+    // start over with their age and stagnation reset
+    pub fn from_snapshot(snapshot: PopulationSnapshot, seed: u64) -> Population {
+        let mut population = Population {
+            genomes: vec![],
+            species: vec![],
+            genome_species: vec![],
+            next_specie_id: snapshot.species.iter().map(|(id, _)| id + 1).max().unwrap_or(0),
+            lineage: vec![],
+            input_num: snapshot.input_num,
+            output_num: snapshot.output_num,
+            hidden_num: snapshot.hidden_num,
+            population_size: snapshot.population_size,
+            pending_size: None,
+            age: snapshot.generation,
+            champion: snapshot.champion,
+            stagnation: 0,
+            innovation_record: snapshot.innovation_record,
+            config: snapshot.config,
+            structural_mutation_probs: None,
+            fitness_cache: HashMap::new(),
+            rng: StdRng::seed_from_u64(seed),
+        };
+        for (specie_id, genomes) in snapshot.species {
+            let mut specie = Specie::new(specie_id, genomes[0].clone());
+            specie.genomes = genomes.clone();
+            population.genome_species.extend(std::iter::repeat_n(specie_id, genomes.len()));
+            population.genomes.extend(genomes);
+            population.species.push(specie);
+        }
         population
     }
 
+    // Disabling structural mutation leaves only weight mutation, e.g. to fine-tune once a target
+    // complexity is reached. Re-enabling restores the previous probabilities
+    pub fn set_structural_mutation_enabled(&mut self, enabled: bool) {
+        match (enabled, self.structural_mutation_probs) {
+            (false, None) => {
+                self.structural_mutation_probs =
+                    Some((self.config.new_node_prob, self.config.new_connection_prob));
+                self.config.new_node_prob = 0.0;
+                self.config.new_connection_prob = 0.0;
+            }
+            (true, Some((new_node_prob, new_connection_prob))) => {
+                self.config.new_node_prob = new_node_prob;
+                self.config.new_connection_prob = new_connection_prob;
+                self.structural_mutation_probs = None;
+            }
+            _ => {}
+        }
+    }
+
+    // Node and connection mutation probabilities for this generation's offspring, after
+    // `config.mutation_schedule`
+    pub fn effective_mutation_probs(&self) -> (f64, f64) {
+        let scale = self.config.mutation_schedule.scale(self.age);
+        (self.config.new_node_prob * scale, self.config.new_connection_prob * scale)
+    }
+
+    // Changes the compatibility threshold from the next speciation on. The threshold is never
+    // adjusted automatically, so it stays at this value
+    pub fn set_compatibility_threshold(&mut self, threshold: f64) -> Result<(), PopulationError> {
+        if threshold <= 0.0 {
+            return Err(PopulationError::InvalidConfig(format!(
+                "compatibility_threshold must be positive, got {}",
+                threshold
+            )));
+        }
+        self.config.compatibility_threshold = threshold;
+        Ok(())
+    }
+
+    // Changes the number of genomes bred from the next `evolve` on. When shrinking, only the
+    // fittest `new_size` genomes of the current generation are kept as parents
+    pub fn resize(&mut self, new_size: usize) -> Result<(), PopulationError> {
+        if new_size == 0 {
+            return Err(PopulationError::EmptyPopulation);
+        }
+        self.population_size = new_size;
+        self.pending_size = Some(new_size);
+        Ok(())
+    }
+
+    pub fn genomes(&self) -> impl Iterator<Item = &Genome> {
+        self.genomes.iter()
+    }
+
+    pub fn genomes_mut(&mut self) -> impl Iterator<Item = &mut Genome> {
+        self.genomes.iter_mut()
+    }
+
+    // Number of genomes in the current generation, which can exceed `population_size` by the
+    // carried over champion
+    pub fn len(&self) -> usize {
+        self.genomes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.genomes.is_empty()
+    }
+
+    pub fn innovation_record(&self) -> &InnovationRecord {
+        &self.innovation_record
+    }
+
+    // Adds a genome from elsewhere, e.g. a champion of an earlier run, to the current generation.
+    // Its hidden nodes and connections are renumbered with this population's innovation record
+    pub fn inject_genome(&mut self, mut genome: Genome) -> Result<(), PopulationError> {
+        if genome.num_inputs() != self.input_num || genome.num_outputs() != self.output_num {
+            return Err(PopulationError::IncompatibleGenome {
+                expected: (self.input_num, self.output_num),
+                found: (genome.num_inputs(), genome.num_outputs()),
+            });
+        }
+        genome.remap_innovations(&mut self.innovation_record);
+        self.genomes.push(genome);
+        // Before the first speciation every genome is still unassigned
+        if !self.species.is_empty() {
+            self.place_genome(self.genomes.len() - 1);
+        }
+        Ok(())
+    }
+
+    // Adds another population's genomes, e.g. from a separate run of the same task, and
+    // re-speciates. Both need the same config apart from the seed. A hidden node of `other` takes
+    // the id of a node of this population that split the same connection, otherwise a new id from
+    // this population's record, the same id for the same node in every genome. Connections are then
+    // renumbered so matching structure shares innovation numbers. This population's size grows by
+    // `other`'s
+    pub fn merge(&mut self, other: Population) -> Result<(), PopulationError> {
+        if other.input_num != self.input_num || other.output_num != self.output_num {
+            return Err(PopulationError::IncompatiblePopulation {
+                expected: (self.input_num, self.output_num),
+                found: (other.input_num, other.output_num),
+            });
+        }
+        let other_config = NeatConfig {
+            seed: self.config.seed,
+            ..other.config.clone()
+        };
+        if other_config != self.config {
+            return Err(PopulationError::ConfigMismatch);
+        }
+
+        let first_hidden = self.input_num + 1 + self.output_num;
+        let mut own_splits: HashMap<(usize, usize), usize> = HashMap::new();
+        for (node, split) in split_connections(&self.innovation_record, first_hidden) {
+            let id = own_splits.entry(split).or_insert(node);
+            *id = node.min(*id);
+        }
+        let other_splits = split_connections(&other.innovation_record, first_hidden);
+
+        let mut hidden: Vec<usize> = other
+            .genomes
+            .iter()
+            .chain(&other.champion)
+            .flat_map(|genome| genome.node.iter())
+            .filter(|node| node.node_type == NodeType::Hidden)
+            .map(|node| node.id)
+            .collect();
+        hidden.sort();
+        hidden.dedup();
+        // Split connections end at older nodes, so they are mapped before the nodes splitting them
+        let mut ids: HashMap<usize, usize> = HashMap::new();
+        let mut used = HashSet::new();
+        for node in hidden {
+            let map = |id: usize| if id < first_hidden { Some(id) } else { ids.get(&id).copied() };
+            let matched = other_splits
+                .get(&node)
+                .and_then(|&(from, to)| own_splits.get(&(map(from)?, map(to)?)))
+                .filter(|id| !used.contains(*id))
+                .copied();
+            let id = matched.unwrap_or_else(|| self.innovation_record.new_node_innovation());
+            used.insert(id);
+            ids.insert(node, id);
+        }
+
+        for mut genome in other.genomes {
+            genome.renumber_nodes(&ids, &mut self.innovation_record);
+            self.genomes.push(genome);
+        }
+        if let Some(mut champion) = other.champion {
+            if self.champion.as_ref().is_none_or(|best| champion.fitness > best.fitness) {
+                champion.renumber_nodes(&ids, &mut self.innovation_record);
+                self.champion = Some(champion);
+            }
+        }
+        self.population_size += other.population_size;
+        self.speciate();
+        Ok(())
+    }
+
+    // Fittest genome of the current generation. Unlike `champion`, which is only updated by
+    // `evolve` and holds the best genome ever seen, this reflects fitness assigned since then.
+    // Genomes with non-finite fitness, which `evolve` would treat as invalid, are skipped
+    pub fn current_best(&self) -> Option<&Genome> {
+        self.genomes
+            .iter()
+            .filter(|genome| genome.fitness.is_finite())
+            .max_by(|a, b| a.fitness.total_cmp(&b.fitness))
+    }
+
+    // Fittest genome of each species by current fitness, with the species id, in order of first
+    // appearance in `genomes`
+    pub fn best_per_species(&self) -> Vec<(usize, &Genome)> {
+        let mut best: Vec<(usize, &Genome)> = vec![];
+        for (genome, &specie_id) in self.genomes.iter().zip(&self.genome_species) {
+            match best.iter_mut().find(|(id, _)| *id == specie_id) {
+                Some(entry) if genome.fitness > entry.1.fitness => entry.1 = genome,
+                Some(_) => {}
+                None => best.push((specie_id, genome)),
+            }
+        }
+        best
+    }
+
+    // Id of the species the genome at `genome_index` was assigned to by the last speciation
+    pub fn species_of(&self, genome_index: usize) -> Option<usize> {
+        self.genome_species.get(genome_index).copied()
+    }
+
+    // Id and number of genomes of each current species
+    pub fn species_sizes(&self) -> Vec<(usize, usize)> {
+        self.species
+            .iter()
+            .map(|specie| (specie.id, specie.genomes.len()))
+            .collect()
+    }
+
+    pub fn get_species(&self, id: usize) -> Option<&Specie> {
+        self.species.iter().find(|specie| specie.id == id)
+    }
+
+    // Every species created so far, in order of creation
+    pub fn lineage(&self) -> &[SpecieOrigin] {
+        &self.lineage
+    }
+
+    // Replaces the least fit `fraction` of genomes with mutated copies of a newly built initial
+    // genome to restore exploration, e.g. once `diversity` drops, then re-speciates
+    pub fn reseed(&mut self, fraction: f64) {
+        let count = (self.genomes.len() as f64 * fraction.clamp(0.0, 1.0)) as usize;
+        self.genomes.sort();
+        self.genomes.truncate(self.genomes.len() - count);
+
+        let genome = Genome::with_config(
+            self.input_num,
+            self.output_num,
+            &mut self.innovation_record,
+            &self.config,
+            &mut self.rng,
+        );
+        for _ in 0..count {
+            let mut new_genome = genome.clone();
+            new_genome.mutate(&mut self.innovation_record, &self.config, &mut self.rng);
+            self.genomes.push(new_genome);
+        }
+        self.speciate();
+    }
+
+    // Drops innovation record entries for connections no genome has anymore, keeping the record
+    // from growing without bound over long runs
+    pub fn compact_innovation_record(&mut self) {
+        let live: HashSet<(usize, usize)> = self
+            .genomes
+            .iter()
+            .chain(&self.champion)
+            .flat_map(|genome| genome.genes.iter().map(|gene| (gene.in_node, gene.out_node)))
+            .collect();
+        self.innovation_record.compact(&live);
+    }
+
+    // Mean compatibility distance between pairs of up to `config.diversity_sample_size` randomly
+    // chosen genomes, low values mean the population has converged
+    pub fn diversity(&mut self) -> f64 {
+        let sample: Vec<&Genome> = self
+            .genomes
+            .choose_multiple(&mut self.rng, self.config.diversity_sample_size)
+            .collect();
+        let mut total = 0.0;
+        let mut pairs = 0;
+        for (i, genome) in sample.iter().enumerate() {
+            for other in &sample[i + 1..] {
+                total += genome.compatability_distance(other, &self.config);
+                pairs += 1;
+            }
+        }
+        if pairs == 0 {
+            0.0
+        } else {
+            total / pairs as f64
+        }
+    }
+
+    // Whether the champion has reached `config.fitness_threshold`
+    pub fn is_solved(&self) -> bool {
+        match (self.config.fitness_threshold, &self.champion) {
+            (Some(threshold), Some(champion)) => champion.fitness >= threshold,
+            _ => false,
+        }
+    }
+
     pub fn get_info(&self) -> String {
         let mut info = String::new();
         info.push_str(&format!("Population Size: {}\n", self.population_size));
@@ -59,6 +562,10 @@ impl Population {
             .fold(0.0, |acc, genome| acc + genome.fitness)
             / self.genomes.len() as f64;
         info.push_str(&format!("Global Average Fitness: {}\n", global_avg_fitness));
+        for specie in &self.species {
+            info.push_str(&format!("Specie {}: Size: {}, Age: {}, Stagnation: {}\n",
+                                   specie.id, specie.genomes.len(), specie.age, specie.stagnation));
+        }
         info
     }
 
@@ -67,87 +574,384 @@ impl Population {
         self.species.retain(|specie| !specie.genomes.is_empty());
 
         for specie in &mut self.species {
-            specie.representative = specie.select_genome();
+            specie.representative = if self.config.deterministic_representative {
+                specie.fittest_genome()
+            } else {
+                specie.select_genome(&mut self.rng)
+            };
             specie.genomes = vec![];
         }
 
-        for genome in &mut self.genomes {
-            let mut found_specie = false;
-            'inner: for specie in &mut self.species {
-                if specie.match_genome(genome) {
-                    specie.add_genome(genome.clone());
-                    found_specie = true;
-                    break 'inner;
-                }
+        self.genome_species.clear();
+        for i in 0..self.genomes.len() {
+            self.place_genome(i);
+        }
+
+        // Remove empty species
+        self.species.retain(|specie| !specie.genomes.is_empty());
+    }
+
+    // Id of the species speciation would put the genome in, or None if it would found a new one.
+    // The population is left unchanged
+    pub fn assign_species(&self, genome: &Genome) -> Option<usize> {
+        self.find_specie(genome).map(|index| self.species[index].id)
+    }
+
+    // Adds the genome at `index` to a compatible species, or founds a new one for it
+    fn place_genome(&mut self, index: usize) {
+        match self.find_specie(&self.genomes[index]) {
+            Some(specie_index) => {
+                self.species[specie_index].add_genome(self.genomes[index].clone());
+                self.genome_species.push(self.species[specie_index].id);
             }
-            if !found_specie {
-                let new_specie = Specie::new(self.species.len(), genome.clone());
+            None => {
+                // The new species branches off the closest existing one
+                let parent_id = self
+                    .nearest_specie(&self.genomes[index])
+                    .map(|(specie_index, _)| self.species[specie_index].id);
+                let mut new_specie = Specie::new(self.next_specie_id, self.genomes[index].clone());
+                new_specie.parent_id = parent_id;
+                self.lineage.push(SpecieOrigin {
+                    id: new_specie.id,
+                    parent_id,
+                    generation: self.age,
+                });
+                self.genome_species.push(new_specie.id);
                 self.species.push(new_specie);
+                self.next_specie_id += 1;
             }
         }
+    }
 
-        // Remove empty species
-        self.species.retain(|specie| !specie.genomes.is_empty());
+    // Index of the species the genome belongs in, if any is compatible
+    fn find_specie(&self, genome: &Genome) -> Option<usize> {
+        match self.config.speciation_mode {
+            SpeciationMode::FirstMatch => self
+                .species
+                .iter()
+                .position(|specie| specie.match_genome(genome, &self.config)),
+            SpeciationMode::NearestRepresentative => self
+                .nearest_specie(genome)
+                .filter(|(_, distance)| *distance < self.config.compatibility_threshold)
+                .map(|(index, _)| index),
+        }
     }
 
-    fn generate_generation(&mut self) -> Vec<Genome> {
+    // Index of and distance to the species with the closest representative
+    fn nearest_specie(&self, genome: &Genome) -> Option<(usize, f64)> {
+        self.species
+            .iter()
+            .map(|specie| specie.representative.speciation_distance(genome, &self.config))
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // Offspring each species gets, in proportion to its adjusted fitness and limited by
+    // `max_species_offspring_fraction`
+    fn offspring_counts(&mut self) -> Vec<usize> {
         // Adjust fitness
         let mut total_adjusted_fitness = 0.0;
         for specie in &mut self.species {
-            total_adjusted_fitness += specie.calculate_average_fitness();
+            total_adjusted_fitness +=
+                specie.calculate_average_fitness(&self.config) * specie.offspring_bonus(&self.config);
         }
         total_adjusted_fitness /= self.population_size as f64;
 
+        // The species with the fittest genomes are kept even when stagnant
+        let protected = self.config.protected_species_count.max(self.config.species_elitism as usize);
+        let mut ranked: Vec<usize> = (0..self.species.len()).collect();
+        ranked.sort_by(|&a, &b| {
+            self.species[b]
+                .best_fitness()
+                .partial_cmp(&self.species[a].best_fitness())
+                .unwrap()
+        });
+        ranked.truncate(protected);
+
+        let mut lost = 0;
+        let mut counts: Vec<usize> = self
+            .species
+            .iter()
+            .enumerate()
+            .map(|(index, specie)| {
+                if specie.genomes.is_empty() {
+                    0
+                } else if specie.stagnation > 15 && !ranked.contains(&index) {
+                    lost += specie.offspring_count(total_adjusted_fitness, &self.config);
+                    0
+                } else {
+                    specie.offspring_count(total_adjusted_fitness, &self.config)
+                }
+            })
+            .collect();
+
+        // Hand what stagnant species would have had to the survivors, in proportion to their own share
+        let surviving: usize = counts.iter().sum();
+        if surviving > 0 {
+            for count in &mut counts {
+                *count += (lost as f64 * *count as f64 / surviving as f64) as usize;
+            }
+        }
+
+        // Hand what the dominant species lose to the others, in proportion to their own share
+        let cap = ((self.population_size as f64 * self.config.max_species_offspring_fraction) as usize).max(1);
+        let excess: usize = counts.iter().map(|count| count.saturating_sub(cap)).sum();
+        let receiving: usize = counts.iter().filter(|count| **count < cap).sum();
+        for count in &mut counts {
+            if *count >= cap {
+                *count = cap;
+            } else if receiving > 0 {
+                let share = (excess as f64 * *count as f64 / receiving as f64) as usize;
+                *count = (*count + share).min(cap);
+            }
+        }
+        counts
+    }
+
+    fn generate_generation(&mut self) -> Vec<Genome> {
+        let offspring_counts = self.offspring_counts();
+        let (new_node_prob, new_connection_prob) = self.effective_mutation_probs();
+        // Offspring mutate with the scheduled probabilities
+        let config = NeatConfig {
+            new_node_prob,
+            new_connection_prob,
+            ..self.config.clone()
+        };
+
         // Generate new generation
         let mut new_genomes = vec![];
-        for specie in &mut self.species {
-            if specie.stagnation > 15 || specie.genomes.is_empty() {
+        for (specie, offspring_num) in self.species.iter_mut().zip(offspring_counts) {
+            if offspring_num == 0 {
                 continue;
             }
-            let specie_size = specie.cull();
-            // dbg!(specie_size);
-            // dbg!(specie.average_fitness);
-            let mut offspring_num = ((specie.average_fitness / total_adjusted_fitness) * specie_size as f64) as usize;
-            // dbg!(offspring_num);
-            if offspring_num < 1 {
-                offspring_num = 1;
-            }
+            specie.cull(&config);
             for _ in 0..offspring_num {
-                let new_genome = specie.make_child(&mut self.innovation_record);
+                let new_genome = specie.make_child(&mut self.innovation_record, &config, &mut self.rng);
                 new_genomes.push(new_genome);
             }
         }
 
         // Add new genomes to fill up population
         while new_genomes.len() < self.population_size {
-            let mut genome = self.genomes.choose().unwrap().clone();
-            genome.mutate(&mut self.innovation_record);
+            let mut genome = self.genomes.choose(&mut self.rng).unwrap().clone();
+            genome.mutate(&mut self.innovation_record, &config, &mut self.rng);
             new_genomes.push(genome);
         }
+        new_genomes.truncate(self.population_size);
+
+        if let Some(threshold) = config.weight_prune_threshold {
+            for genome in &mut new_genomes {
+                genome.prune_weak_connections(threshold);
+            }
+        }
 
         new_genomes
     }
 
     pub fn evolve(&mut self) {
+        self.sanitize_fitness();
         // Get new champion
-        self.genomes.sort();
+        self.sort_genomes();
+        self.refresh_species();
         let champion = self.genomes[0].clone();
         if self.champion.is_none() || champion.fitness > self.champion.as_ref().unwrap().fitness {
             self.champion = Some(champion.clone());
+            self.stagnation = 0;
+        } else {
+            self.stagnation += 1;
+        }
+        if self.stagnation > self.config.global_stagnation_limit {
+            self.refocus();
+        }
+
+        // Cull the least fit after the population was shrunk
+        if self.pending_size.take().is_some_and(|size| self.genomes.len() > size) {
+            self.genomes.truncate(self.population_size);
+            self.speciate();
         }
 
         // Generate new generation
         let mut new_genomes = self.generate_generation();
-        // Add champion to new generation
-        new_genomes.push(champion);
+        if self.config.global_elitism == 0 {
+            // Add champion to new generation
+            new_genomes.push(champion);
+        } else {
+            // The fittest genomes, champion included, replace offspring unchanged
+            let elites = self.config.global_elitism.min(self.population_size);
+            new_genomes.truncate(self.population_size - elites);
+            new_genomes.extend(self.genomes.iter().take(elites).cloned());
+        }
         self.genomes = new_genomes;
+        for specie in &mut self.species {
+            specie.age += 1;
+        }
         self.speciate();
         self.age += 1;
     }
 
+    // Replaces non-finite fitness with `config.invalid_fitness_value` so such genomes rank last
+    // instead of breaking sorting, returning how many were replaced
+    fn sanitize_fitness(&mut self) -> usize {
+        let mut count = 0;
+        for genome in self.genomes.iter_mut().filter(|genome| !genome.fitness.is_finite()) {
+            genome.fitness = self.config.invalid_fitness_value;
+            count += 1;
+        }
+        count
+    }
+
+    // Sorts genomes fittest first, keeping their species assignments in step
+    fn sort_genomes(&mut self) {
+        if self.genome_species.len() != self.genomes.len() {
+            self.genomes.sort();
+            return;
+        }
+        let mut pairs: Vec<(Genome, usize)> = std::mem::take(&mut self.genomes)
+            .into_iter()
+            .zip(std::mem::take(&mut self.genome_species))
+            .collect();
+        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+        (self.genomes, self.genome_species) = pairs.into_iter().unzip();
+    }
+
+    // Replaces the copies species took of their members at speciation with the evaluated genomes,
+    // so species are ranked, culled and bred by the fitness just assigned
+    fn refresh_species(&mut self) {
+        if self.genome_species.len() != self.genomes.len() {
+            return;
+        }
+        for specie in &mut self.species {
+            specie.genomes = self
+                .genomes
+                .iter()
+                .zip(&self.genome_species)
+                .filter(|(_, specie_id)| **specie_id == specie.id)
+                .map(|(genome, _)| genome.clone())
+                .collect();
+        }
+        self.species.retain(|specie| !specie.genomes.is_empty());
+    }
+
+    // Keeps only the members of the two species with the fittest evaluated genomes, so the whole
+    // next generation is bred from them
+    fn refocus(&mut self) {
+        let mut best: Vec<(usize, f64)> = self
+            .best_per_species()
+            .into_iter()
+            .map(|(specie_id, genome)| (specie_id, genome.fitness))
+            .collect();
+        if best.is_empty() {
+            return;
+        }
+        best.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let kept: Vec<usize> = best.iter().take(2).map(|(specie_id, _)| *specie_id).collect();
+
+        (self.genomes, self.genome_species) = std::mem::take(&mut self.genomes)
+            .into_iter()
+            .zip(std::mem::take(&mut self.genome_species))
+            .filter(|(_, specie_id)| kept.contains(specie_id))
+            .unzip();
+        self.species.retain(|specie| kept.contains(&specie.id));
+        self.stagnation = 0;
+    }
+
     pub fn evaluate(&mut self, f: &dyn Fn(&mut Genome, bool)) {
+        self.assign_fitness(f);
+        self.evolve();
+    }
+
+    // Scores the current generation like `evaluate` without evolving it
+    pub(crate) fn assign_fitness(&mut self, f: &dyn Fn(&mut Genome, bool)) {
+        if !self.config.cache_fitness {
+            for genome in &mut self.genomes {
+                f(genome, false);
+            }
+            return;
+        }
+        let mut cache = HashMap::new();
         for genome in &mut self.genomes {
-            f(genome, false);
+            let hash = genome.structural_hash();
+            match self.fitness_cache.get(&hash).or(cache.get(&hash)) {
+                Some(&fitness) => genome.fitness = fitness,
+                None => f(genome, false),
+            }
+            cache.insert(hash, genome.fitness);
+        }
+        self.fitness_cache = cache;
+    }
+
+    // Evaluates and evolves generations until `duration` has passed, checking the clock between
+    // generations, and returns the number of generations run. At least one generation is run
+    pub fn run_for(
+        &mut self,
+        duration: Duration,
+        f: &dyn Fn(&mut Genome, bool),
+        on_generation: &mut dyn FnMut(&Population),
+    ) -> usize {
+        let start = Instant::now();
+        let mut generations = 0;
+        loop {
+            self.evaluate(f);
+            generations += 1;
+            on_generation(self);
+            if start.elapsed() >= duration {
+                return generations;
+            }
+        }
+    }
+
+    // Weight agnostic evaluation: each genome is scored with all of its connection weights set to
+    // each of `shared_weights` in turn and given the mean fitness, rewarding topology over weights.
+    // Panics if `shared_weights` is empty
+    pub fn evaluate_weight_agnostic(&mut self, shared_weights: &[f64], f: &dyn Fn(&mut Genome, bool)) {
+        assert!(!shared_weights.is_empty(), "at least one shared weight");
+        for genome in &mut self.genomes {
+            let weights: Vec<f64> = genome.genes.iter().map(|gene| gene.weight).collect();
+            let mut total = 0.0;
+            for &shared_weight in shared_weights {
+                genome.genes.iter_mut().for_each(|gene| gene.weight = shared_weight);
+                f(genome, false);
+                total += genome.fitness;
+            }
+            for (gene, weight) in genome.genes.iter_mut().zip(weights) {
+                gene.weight = weight;
+            }
+            genome.fitness = total / shared_weights.len() as f64;
+        }
+        self.evolve();
+    }
+
+    // Scores each genome on `num_cases` independent cases, such as different starting conditions,
+    // with the mean as fitness. Panics if `num_cases` is 0
+    pub fn evaluate_multi(&mut self, num_cases: usize, f: &dyn Fn(&Genome, usize) -> f64) {
+        assert!(num_cases > 0, "at least one case");
+        for genome in &mut self.genomes {
+            genome.fitness = (0..num_cases).map(|case| f(genome, case)).sum::<f64>() / num_cases as f64;
+        }
+        self.evolve();
+    }
+
+    // Runs each genome through `episodes` episodes of the environment, feeding observations to the
+    // network and its outputs back as actions, with the summed reward as fitness
+    pub fn evaluate_episodes(&mut self, environment: &mut dyn Environment, episodes: usize) {
+        self.assign_episode_fitness(environment, episodes);
+        self.evolve();
+    }
+
+    // Scores the current generation like `evaluate_episodes` without evolving it
+    pub(crate) fn assign_episode_fitness(&mut self, environment: &mut dyn Environment, episodes: usize) {
+        for genome in &mut self.genomes {
+            genome.fitness = run_episodes(environment, &genome.compile(&self.config), episodes);
+        }
+    }
+
+    // Evaluation for stochastic tasks: every genome is given a random number generator seeded the
+    // same way, so within a generation all genomes face the same random scenarios. The seed is
+    // drawn from the population's generator and changes each generation
+    pub fn evaluate_seeded(&mut self, f: &dyn Fn(&Genome, &mut StdRng) -> f64) {
+        let seed = self.rng.gen::<u64>();
+        for genome in &mut self.genomes {
+            genome.fitness = f(genome, &mut StdRng::seed_from_u64(seed));
         }
         self.evolve();
     }
@@ -157,3 +961,1120 @@ impl Population {
         self.evolve();
     }
 }
+
+// Connection each hidden node of the record split when it was added: the one between a source
+// and a target the node connects, taking the node's oldest connections when there are several
+fn split_connections(
+    innovation_record: &InnovationRecord,
+    first_hidden: usize,
+) -> HashMap<usize, (usize, usize)> {
+    let connections = &innovation_record.innovation_number;
+    let mut outgoing: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &(from, to) in connections.keys() {
+        outgoing.entry(from).or_default().push(to);
+    }
+    // Node -> (innovation numbers of its two connections, split connection)
+    let mut splits: HashMap<usize, ([usize; 2], (usize, usize))> = HashMap::new();
+    for (&(from, node), &incoming) in connections {
+        if node < first_hidden {
+            continue;
+        }
+        for &to in outgoing.get(&node).into_iter().flatten() {
+            if !connections.contains_key(&(from, to)) {
+                continue;
+            }
+            let age = [incoming, connections[&(node, to)]];
+            let split = splits.entry(node).or_insert((age, (from, to)));
+            if age < split.0 {
+                *split = (age, (from, to));
+            }
+        }
+    }
+    splits.into_iter().map(|(node, (_, split))| (node, split)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CrossoverMode, MutationSchedule};
+    use crate::genes::{ActivationFunction, ConnectionGene, NodeGene};
+
+    // Species with ids counting from 0, each holding `members` copies of `genome` with the
+    // species' fitness
+    fn species_with_fitness(genome: &Genome, fitnesses: &[f64], members: usize) -> Vec<Specie> {
+        fitnesses
+            .iter()
+            .enumerate()
+            .map(|(id, &fitness)| {
+                let mut member = genome.clone();
+                member.fitness = fitness;
+                let mut specie = Specie::new(id, member.clone());
+                for _ in 1..members {
+                    specie.add_genome(member.clone());
+                }
+                specie
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seeded_evaluation_shares_scenarios() {
+        let mut population = Population::new(10, 2, 1, 0).unwrap();
+        let draws = std::cell::RefCell::new(vec![]);
+        let record = |_: &Genome, rng: &mut StdRng| {
+            let scenario: Vec<u64> = (0..3).map(|_| rng.gen()).collect();
+            draws.borrow_mut().push(scenario);
+            0.0
+        };
+
+        population.evaluate_seeded(&record);
+        let first: Vec<Vec<u64>> = draws.borrow_mut().drain(..).collect();
+        assert_eq!(first.len(), 10);
+        assert!(first.iter().all(|scenario| *scenario == first[0]));
+
+        population.evaluate_seeded(&record);
+        assert_ne!(draws.borrow()[0], first[0]);
+    }
+
+    #[test]
+    fn global_stagnation_refocus() {
+        let mut population = Population::new(30, 2, 1, 0).unwrap();
+        population.config.global_stagnation_limit = 3;
+
+        // Give every generation the same fitness so the champion never improves
+        for _ in 0..4 {
+            population.evaluate(&|genome, _| genome.fitness = 1.0);
+        }
+        assert_eq!(population.stagnation, 3);
+
+        population.evaluate(&|genome, _| genome.fitness = 1.0);
+        assert_eq!(population.stagnation, 0);
+        // The generation is refilled from the two remaining species, plus the champion
+        assert_eq!(population.len(), 31);
+    }
+
+    #[test]
+    fn refocus_keeps_best_species() {
+        let mut population = Population::new(30, 2, 1, 0).unwrap();
+        let genome = population.genomes[0].clone();
+        // Species hold copies of their members from before evaluation
+        population.species = (0..5).map(|i| Specie::new(i, genome.clone())).collect();
+        population.genomes = [3.0, 1.0, 4.0, 0.5, 2.0]
+            .iter()
+            .flat_map(|&fitness| {
+                let mut member = genome.clone();
+                member.fitness = fitness;
+                [member.clone(), member]
+            })
+            .collect();
+        population.genome_species = (0..5).flat_map(|i| [i, i]).collect();
+        population.stagnation = 25;
+
+        population.refresh_species();
+        population.refocus();
+        let mut ids: Vec<usize> = population.species.iter().map(|specie| specie.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![0, 2]);
+        assert_eq!(population.genomes.len(), 4);
+        assert!(population.genomes().all(|genome| genome.fitness >= 3.0));
+        assert!(population.species.iter().all(|specie| specie.best_fitness() >= 3.0));
+        assert_eq!(population.stagnation, 0);
+    }
+
+    #[test]
+    fn species_use_evaluated_fitness() {
+        let config = NeatConfig {
+            compatibility_threshold: 0.5,
+            seed: Some(2),
+            ..NeatConfig::default()
+        };
+        let mut population = Population::with_config(30, 2, 1, 0, config).unwrap();
+        population.evaluate(&|genome, _| genome.fitness = 1.0);
+        assert!(population.species.len() > 1);
+
+        // Only the first genome's species scores this generation
+        let assigned = population.genome_species.clone();
+        let favored = assigned[0];
+        let index = std::cell::Cell::new(0);
+        population.assign_fitness(&|genome, _| {
+            genome.fitness = if assigned[index.get()] == favored { 10.0 } else { 0.0 };
+            index.set(index.get() + 1);
+        });
+        population.evolve();
+
+        for specie in population.species.iter().filter(|specie| assigned.contains(&specie.id)) {
+            if specie.id == favored {
+                assert!(specie.average_fitness > 0.0);
+            } else {
+                assert_eq!(specie.average_fitness, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_representative_speciation() {
+        let mut population = Population::new(10, 2, 1, 0).unwrap();
+        population.config.speciation_mode = SpeciationMode::NearestRepresentative;
+
+        // Representatives that only differ in weights
+        let base = population.genomes[0].clone();
+        let with_weight = |weight: f64| {
+            let mut genome = base.clone();
+            genome.genes.iter_mut().for_each(|gene| gene.weight = weight);
+            genome
+        };
+        population.species = vec![Specie::new(0, with_weight(0.0)), Specie::new(1, with_weight(3.0))];
+
+        // Every genome is within the threshold of both, so the first species would always match
+        let weights = [0.5, 1.0, 2.0, 2.5, 1.4, 1.6];
+        let mut genomes: Vec<Genome> = weights.iter().map(|w| with_weight(*w)).collect();
+        let assign = |genomes: &Vec<Genome>| -> Vec<(i64, usize)> {
+            let mut assignments: Vec<(i64, usize)> = genomes
+                .iter()
+                .map(|genome| {
+                    let index = population.find_specie(genome).unwrap();
+                    ((genome.genes[0].weight * 10.0) as i64, population.species[index].id)
+                })
+                .collect();
+            assignments.sort();
+            assignments
+        };
+        let assignments = assign(&genomes);
+        genomes.reverse();
+        assert_eq!(assign(&genomes), assignments);
+        assert_eq!(assignments, vec![(5, 0), (10, 0), (14, 0), (16, 1), (20, 1), (25, 1)]);
+
+        population.config.speciation_mode = SpeciationMode::FirstMatch;
+        assert!(genomes.iter().all(|genome| population.find_specie(genome) == Some(0)));
+    }
+
+    #[test]
+    fn current_best_before_evolve() {
+        let mut population = Population::new(10, 2, 1, 0).unwrap();
+        population.evaluate(&|genome, _| genome.fitness = 1.0);
+        assert_eq!(population.champion.as_ref().unwrap().fitness, 1.0);
+
+        // Evaluate without evolving, as when driving the genomes directly
+        for (i, genome) in population.genomes.iter_mut().enumerate() {
+            genome.fitness = i as f64 + 2.0;
+        }
+        let best = population.current_best().unwrap();
+        assert_eq!(best.fitness, population.genomes.len() as f64 + 1.0);
+        assert_eq!(population.champion.as_ref().unwrap().fitness, 1.0);
+
+        population.genomes[0].fitness = f64::NAN;
+        population.genomes[1].fitness = f64::INFINITY;
+        assert_eq!(population.current_best().unwrap().fitness, population.genomes.len() as f64 + 1.0);
+        population.genomes_mut().for_each(|genome| genome.fitness = f64::NAN);
+        assert!(population.current_best().is_none());
+    }
+
+    #[test]
+    fn species_lineage() {
+        let mut population = Population::new(10, 2, 1, 0).unwrap();
+        assert_eq!(population.species_of(0), None);
+
+        let mut close = population.genomes[0].clone();
+        close.genes.iter_mut().for_each(|gene| gene.weight = 0.0);
+        let mut far = close.clone();
+        far.genes.iter_mut().for_each(|gene| gene.weight = 10.0);
+
+        // One existing species the far genome is incompatible with
+        population.species = vec![Specie::new(0, close.clone())];
+        population.next_specie_id = 1;
+        population.genomes = vec![close, far];
+        population.speciate();
+
+        assert_eq!(population.species.len(), 2);
+        assert_eq!(population.species[1].parent_id, Some(0));
+        assert_eq!(population.species_of(0), Some(0));
+        assert_eq!(population.species_of(1), Some(1));
+        assert_eq!(
+            population.lineage().last(),
+            Some(&SpecieOrigin { id: 1, parent_id: Some(0), generation: 0 })
+        );
+    }
+
+    #[test]
+    fn invalid_population() {
+        assert_eq!(Population::new(0, 2, 1, 0).err(), Some(PopulationError::EmptyPopulation));
+        assert_eq!(Population::new(10, 0, 1, 0).err(), Some(PopulationError::NoInputs));
+        assert_eq!(Population::new(10, 2, 0, 0).err(), Some(PopulationError::NoOutputs));
+
+        let config = NeatConfig {
+            survival_threshold: 1.5,
+            ..NeatConfig::default()
+        };
+        let error = Population::with_config(10, 2, 1, 0, config).err();
+        assert!(matches!(error, Some(PopulationError::InvalidConfig(_))));
+
+        let config = NeatConfig {
+            compatibility_threshold: 0.0,
+            ..NeatConfig::default()
+        };
+        let error = Population::with_config(10, 2, 1, 0, config).err();
+        assert!(matches!(error, Some(PopulationError::InvalidConfig(_))));
+
+        let config = NeatConfig {
+            output_activation_functions: Some(vec![]),
+            ..NeatConfig::default()
+        };
+        let error = Population::with_config(10, 2, 1, 0, config).err();
+        assert!(matches!(error, Some(PopulationError::InvalidConfig(_))));
+
+        let config = NeatConfig {
+            output_activation_functions: Some(vec![ActivationFunction::Custom(0)]),
+            ..NeatConfig::default()
+        };
+        let error = Population::with_config(10, 2, 1, 0, config).err();
+        assert!(matches!(error, Some(PopulationError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn weight_only_mutation() {
+        let mut population = Population::new(30, 2, 1, 0).unwrap();
+        let config = NeatConfig::default();
+        let evaluate = |genome: &mut Genome, _| genome.fitness = genome.feed_forward(vec![1.0, 0.0], &config)[0];
+        for _ in 0..5 {
+            population.evaluate(&evaluate);
+        }
+
+        population.set_structural_mutation_enabled(false);
+        assert_eq!(population.config.new_node_prob, 0.0);
+        assert_eq!(population.config.new_connection_prob, 0.0);
+        let sizes: HashSet<(usize, usize)> = population
+            .genomes
+            .iter()
+            .map(|genome| (genome.node.len(), genome.genes.len()))
+            .collect();
+        let all_weights = |population: &Population| -> Vec<f64> {
+            population
+                .genomes
+                .iter()
+                .flat_map(|genome| genome.genes.iter().map(|gene| gene.weight))
+                .collect()
+        };
+        let weights = all_weights(&population);
+
+        for _ in 0..20 {
+            population.evaluate(&evaluate);
+        }
+        // Genomes only inherit existing structures while weights keep changing
+        for genome in &population.genomes {
+            assert!(sizes.contains(&(genome.node.len(), genome.genes.len())));
+        }
+        assert_ne!(weights, all_weights(&population));
+
+        population.set_structural_mutation_enabled(true);
+        assert_eq!(population.config.new_node_prob, NeatConfig::default().new_node_prob);
+        assert_eq!(population.config.new_connection_prob, NeatConfig::default().new_connection_prob);
+    }
+
+    fn xor_fitness(genome: &mut Genome, _display: bool) {
+        let xor = [([0.0, 0.0], 0.0), ([0.0, 1.0], 1.0), ([1.0, 0.0], 1.0), ([1.0, 1.0], 0.0)];
+        let config = NeatConfig::default();
+        let error: f64 = xor
+            .iter()
+            .map(|(input, output)| (genome.feed_forward(input.to_vec(), &config)[0] - output).powi(2))
+            .sum();
+        genome.fitness = 4.0 - error;
+    }
+
+    #[test]
+    fn solved_xor() {
+        let mut population = Population::new(20, 2, 1, 0).unwrap();
+        population.config.fitness_threshold = Some(3.9);
+        assert!(!population.is_solved());
+
+        population.evaluate(&xor_fitness);
+        assert_eq!(population.is_solved(), population.champion.as_ref().unwrap().fitness >= 3.9);
+
+        // Hand-built XOR network: hidden OR and AND nodes feeding the output
+        let mut genome = population.genomes[0].clone();
+        genome.genes.clear();
+        genome.node.truncate(4);
+        genome.node.push(NodeGene::new(4, NodeType::Hidden, 2, 0.0, 0.0));
+        genome.node.push(NodeGene::new(5, NodeType::Hidden, 2, 0.0, 0.0));
+        let connections = [
+            (0, 4, 10.0),
+            (1, 4, 10.0),
+            (2, 4, -5.0),
+            (0, 5, 10.0),
+            (1, 5, 10.0),
+            (2, 5, -15.0),
+            (4, 3, 10.0),
+            (5, 3, -10.0),
+            (2, 3, -5.0),
+        ];
+        for (i, (from, to, weight)) in connections.into_iter().enumerate() {
+            genome.genes.push(ConnectionGene::new(from, to, weight, 100 + i));
+        }
+        genome.recalculate_layers();
+        let mut solution = genome.clone();
+        xor_fitness(&mut solution, false);
+        assert!(solution.fitness > 3.9);
+
+        population.genomes[0] = genome;
+        population.evaluate(&xor_fitness);
+        assert!(population.is_solved());
+    }
+
+    #[test]
+    fn shared_innovation_record() {
+        // Two populations sharing a record should give identical connections identical innovations
+        let population_1 = Population::new(20, 2, 1, 0).unwrap();
+        let record = population_1.innovation_record().clone();
+        let population_2 = Population::with_innovation_record(20, 2, 1, 0, record).unwrap();
+
+        let mut innovations: HashMap<(usize, usize), usize> = HashMap::new();
+        for genome in population_1.genomes.iter().chain(population_2.genomes.iter()) {
+            for gene in &genome.genes {
+                let innovation = innovations
+                    .entry((gene.in_node, gene.out_node))
+                    .or_insert(gene.innovation);
+                assert_eq!(*innovation, gene.innovation);
+            }
+        }
+        // Input, bias and output nodes keep the same ids in both populations
+        let ids_1: Vec<usize> = population_1.genomes[0].node[..4].iter().map(|node| node.id).collect();
+        let ids_2: Vec<usize> = population_2.genomes[0].node[..4].iter().map(|node| node.id).collect();
+        assert_eq!(ids_1, ids_2);
+    }
+
+    #[test]
+    fn deterministic_representative() {
+        let config = NeatConfig {
+            deterministic_representative: true,
+            ..NeatConfig::default()
+        };
+        let mut population_1 = Population::with_config(30, 2, 1, 0, config.clone()).unwrap();
+        let mut population_2 = Population::with_config(30, 2, 1, 0, config).unwrap();
+        population_2.genomes = population_1.genomes.clone();
+
+        for generation in 0..5 {
+            for population in [&mut population_1, &mut population_2] {
+                for (i, genome) in population.genomes.iter_mut().enumerate() {
+                    genome.fitness = (i + generation) as f64;
+                }
+                population.speciate();
+            }
+            let representatives = |population: &Population| {
+                population
+                    .species
+                    .iter()
+                    .map(|specie| specie.representative.fitness)
+                    .collect::<Vec<f64>>()
+            };
+            assert_eq!(representatives(&population_1), representatives(&population_2));
+        }
+    }
+
+    #[test]
+    fn species_age() {
+        // A threshold no genome can exceed keeps everything in the first species
+        let config = NeatConfig {
+            compatibility_threshold: f64::MAX,
+            ..NeatConfig::default()
+        };
+        let mut population = Population::with_config(20, 2, 1, 0, config).unwrap();
+        // The first evolve creates the species
+        population.evaluate(&|genome, _| genome.fitness = 1.0);
+        assert_eq!(population.species[0].age, 0);
+
+        for _ in 0..5 {
+            population.evaluate(&|genome, _| genome.fitness = 1.0);
+        }
+        assert_eq!(population.species.len(), 1);
+        assert_eq!(population.species[0].id, 0);
+        assert_eq!(population.species[0].age, 5);
+        assert!(population.get_info().contains(&format!(
+            "Specie 0: Size: {}, Age: 5",
+            population.genomes.len()
+        )));
+    }
+
+    #[test]
+    fn genome_iterators() {
+        let mut population = Population::new(25, 2, 1, 0).unwrap();
+        assert_eq!(population.genomes().count(), 25);
+        assert_eq!(population.len(), 25);
+        assert!(!population.is_empty());
+
+        for (i, genome) in population.genomes_mut().enumerate() {
+            genome.fitness = i as f64;
+        }
+        let total: f64 = population.genomes().map(|genome| genome.fitness).sum();
+        assert_eq!(total, (0..25).sum::<usize>() as f64);
+    }
+
+    #[test]
+    fn species_offspring_cap() {
+        let mut population = Population::new(40, 2, 1, 0).unwrap();
+        let genome = population.genomes[0].clone();
+        population.species = (0..4)
+            .map(|id| {
+                let mut member = genome.clone();
+                member.fitness = if id == 0 { 10.0 } else { 1.0 };
+                let mut specie = Specie::new(id, member.clone());
+                for _ in 1..10 {
+                    specie.add_genome(member.clone());
+                }
+                specie
+            })
+            .collect();
+
+        // Uncapped the dominant species would get 10/13 of the population
+        let counts = population.offspring_counts();
+        assert_eq!(counts[0], 20);
+        assert!(counts[1..].iter().all(|count| *count == 6));
+        assert!(counts.iter().sum::<usize>() <= 40);
+    }
+
+    #[test]
+    fn stagnant_species_quota() {
+        let mut population = Population::new(40, 2, 1, 0).unwrap();
+        population.config.max_species_offspring_fraction = 1.0;
+        let genome = population.genomes[0].clone();
+        population.species = species_with_fitness(&genome, &[2.0, 1.0, 1.0, 1.0], 10);
+        let before = population.offspring_counts();
+
+        population.species[3].stagnation = 20;
+        let counts = population.offspring_counts();
+        assert_eq!(counts[3], 0);
+        assert_eq!(counts.iter().sum::<usize>(), before.iter().sum::<usize>());
+        assert!(counts[..3].iter().zip(&before).all(|(after, before)| after > before));
+        assert!(counts[0] > counts[1]);
+    }
+
+    #[test]
+    fn invalid_fitness() {
+        let mut population = Population::new(20, 2, 1, 0).unwrap();
+        for (i, genome) in population.genomes.iter_mut().enumerate() {
+            genome.fitness = match i % 4 {
+                0 => f64::NAN,
+                1 => f64::INFINITY,
+                _ => i as f64,
+            };
+        }
+        assert_eq!(population.sanitize_fitness(), 10);
+        population.genomes.sort();
+        assert!(population.genomes[..10].iter().all(|genome| genome.fitness >= 2.0));
+        assert!(population.genomes[10..].iter().all(|genome| genome.fitness == f64::MIN));
+
+        for _ in 0..5 {
+            population.evaluate(&|genome: &mut Genome, _| {
+                genome.fitness = if genome.genes.len().is_multiple_of(2) { f64::NAN } else { 1.0 }
+            });
+        }
+        assert!(population.champion.unwrap().fitness.is_finite());
+    }
+
+    #[test]
+    fn inject_genome() {
+        // Champion of another run with its own innovation record
+        let mut other = Population::new(30, 2, 1, 0).unwrap();
+        for _ in 0..10 {
+            other.evaluate(&|genome, _| genome.fitness = genome.genes.len() as f64);
+        }
+        let mut champion = other.champion.clone().unwrap();
+        let mut record = other.innovation_record().clone();
+        for _ in 0..5 {
+            champion.add_node(&mut record, &other.config, &mut rand::thread_rng());
+        }
+        let connections = champion.genes.len();
+
+        let mut population = Population::new(30, 2, 1, 0).unwrap();
+        population.evaluate(&|genome, _| genome.fitness = 1.0);
+        let num_nodes = population.innovation_record().num_nodes;
+        population.inject_genome(champion).unwrap();
+
+        let injected = population.genomes.last().unwrap();
+        assert!(injected
+            .node
+            .iter()
+            .filter(|node| node.node_type == NodeType::Hidden)
+            .all(|node| node.id >= num_nodes));
+        for gene in &injected.genes {
+            assert_eq!(
+                population.innovation_record().innovation_number[&(gene.in_node, gene.out_node)],
+                gene.innovation
+            );
+        }
+        assert_eq!(population.genome_species.len(), population.genomes.len());
+
+        // Only the injected genome is fit, so it becomes the champion and a parent
+        population.evaluate(&|genome, _| {
+            genome.fitness = if genome.genes.len() == connections { 10.0 } else { 0.0 }
+        });
+        assert_eq!(population.champion.as_ref().unwrap().genes.len(), connections);
+
+        assert_eq!(
+            population.inject_genome(Genome::new(3, 1, &mut InnovationRecord::new())),
+            Err(PopulationError::IncompatibleGenome {
+                expected: (2, 1),
+                found: (3, 1),
+            })
+        );
+    }
+
+    #[test]
+    fn behavioral_speciation() {
+        let mut population = Population::new(2, 2, 1, 0).unwrap();
+        population.config.compatibility_threshold = 0.5;
+        let mut innovation_record = population.innovation_record().clone();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        let mut other = genome.clone();
+        for _ in 0..5 {
+            other.add_node(&mut innovation_record, &population.config, &mut rand::thread_rng());
+        }
+        genome.behavior = Some(vec![0.0, 1.0, 1.0, 0.0]);
+        other.behavior = genome.behavior.clone();
+        assert!(genome.compatability_distance(&other, &population.config) > population.config.compatibility_threshold);
+        population.genomes = vec![genome, other];
+
+        population.speciate();
+        assert_ne!(population.species_of(0), population.species_of(1));
+
+        population.species.clear();
+        population.config.use_behavioral_speciation = true;
+        population.speciate();
+        assert_eq!(population.species_of(0), population.species_of(1));
+
+        // Without a behavior the genomes fall back to their compatibility distance
+        population.species.clear();
+        population.genomes[1].behavior = None;
+        population.speciate();
+        assert_ne!(population.species_of(0), population.species_of(1));
+    }
+
+    #[test]
+    fn seeded_runs_match() {
+        let run = |seed: u64| {
+            let config = NeatConfig {
+                seed: Some(seed),
+                ..NeatConfig::default()
+            };
+            let mut population = Population::with_config(50, 2, 1, 0, config).unwrap();
+            for _ in 0..20 {
+                population.evaluate(&xor_fitness);
+            }
+            population.champion.unwrap()
+        };
+        let champion = run(7);
+        let other = run(7);
+        assert_eq!(champion.fitness, other.fitness);
+        assert_eq!(format!("{:?}", champion.genes), format!("{:?}", other.genes));
+        assert_eq!(format!("{:?}", champion.node), format!("{:?}", other.node));
+    }
+
+    #[test]
+    fn seeded_runs_match_across_threads() {
+        // Hash maps on different threads are keyed differently, so matching runs show evolution
+        // doesn't depend on hash map iteration order
+        let run = || {
+            std::thread::spawn(|| {
+                let config = NeatConfig {
+                    seed: Some(11),
+                    speciation_mode: SpeciationMode::NearestRepresentative,
+                    crossover_mode: CrossoverMode::AverageWeights,
+                    activation_mutation_prob: 0.2,
+                    ..NeatConfig::default()
+                };
+                let mut population = Population::with_config(40, 3, 2, 0, config).unwrap();
+                for _ in 0..15 {
+                    population.evaluate(&|genome, _| {
+                        let outputs = genome.feed_forward(vec![0.5, -1.0, 2.0], &NeatConfig::default());
+                        genome.fitness = outputs.iter().sum::<f64>()
+                    });
+                }
+                (format!("{:?}", population.genomes), population.genome_species.clone())
+            })
+            .join()
+            .unwrap()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn global_elitism() {
+        let config = NeatConfig {
+            global_elitism: 3,
+            ..NeatConfig::default()
+        };
+        let mut population = Population::with_config(20, 2, 1, 0, config).unwrap();
+        for (i, genome) in population.genomes.iter_mut().enumerate() {
+            genome.fitness = i as f64;
+        }
+        let mut best = population.genomes.clone();
+        best.sort();
+        best.truncate(3);
+
+        population.evolve();
+        assert_eq!(population.genomes.len(), 20);
+        for elite in &best {
+            assert!(population
+                .genomes
+                .iter()
+                .any(|genome| format!("{:?}", genome.genes) == format!("{:?}", elite.genes)));
+        }
+    }
+
+    #[test]
+    fn diversity() {
+        let mut population = Population::new(40, 2, 1, 0).unwrap();
+        let diverse = population.diversity();
+
+        population.genomes = vec![population.genomes[0].clone(); 40];
+        assert_eq!(population.diversity(), 0.0);
+        assert!(diverse > 0.0);
+    }
+
+    #[test]
+    fn weight_agnostic_evaluation() {
+        let mut population = Population::new(20, 2, 1, 0).unwrap();
+        let original: HashSet<String> = population
+            .genomes
+            .iter()
+            .map(|genome| format!("{:?}", genome.genes))
+            .collect();
+
+        // Fitness is the shared weight itself, so every genome scores the mean
+        population.evaluate_weight_agnostic(&[-2.0, -1.0, 0.5, 1.0, 2.0], &|genome, _| {
+            genome.fitness = genome.genes[0].weight
+        });
+        let champion = population.champion.as_ref().unwrap();
+        assert!((champion.fitness - 0.1).abs() < 1e-12);
+        // Weights are restored before the population evolves
+        assert!(original.contains(&format!("{:?}", champion.genes)));
+    }
+
+    #[test]
+    fn reseed() {
+        let mut population = Population::new(40, 2, 1, 0).unwrap();
+        let mut innovation_record = population.innovation_record().clone();
+        let mut converged = population.genomes[0].clone();
+        for _ in 0..5 {
+            converged.add_node(&mut innovation_record, &population.config, &mut rand::thread_rng());
+        }
+        population.innovation_record = innovation_record;
+        population.genomes = vec![converged; 40];
+        assert_eq!(population.diversity(), 0.0);
+
+        population.reseed(0.5);
+        assert_eq!(population.genomes.len(), 40);
+        assert_eq!(population.genome_species.len(), 40);
+        assert!(population.diversity() > 0.0);
+    }
+
+    #[test]
+    fn nan_weight_speciation() {
+        let mut population = Population::new(20, 2, 1, 0).unwrap();
+        population.genomes[0].genes[0].weight = f64::NAN;
+        population.speciate();
+        assert_eq!(population.genome_species.len(), 20);
+    }
+
+    #[test]
+    fn best_per_species() {
+        let mut population = Population::new(40, 2, 1, 0).unwrap();
+        population.config.compatibility_threshold = 0.3;
+        population.speciate();
+        for (i, genome) in population.genomes.iter_mut().enumerate() {
+            genome.fitness = ((i * 7) % 40) as f64;
+        }
+
+        let best = population.best_per_species();
+        let species: HashSet<usize> = population.genome_species.iter().copied().collect();
+        assert!(species.len() > 1);
+        assert_eq!(best.len(), species.len());
+        for (specie_id, genome) in best {
+            let max_fitness = population
+                .genomes
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| population.species_of(*i) == Some(specie_id))
+                .fold(f64::MIN, |acc, (_, genome)| acc.max(genome.fitness));
+            assert_eq!(genome.fitness, max_fitness);
+        }
+    }
+
+    #[test]
+    fn species_sizes() {
+        let mut population = Population::new(40, 2, 1, 0).unwrap();
+        population.config.compatibility_threshold = 0.3;
+        population.speciate();
+
+        let sizes = population.species_sizes();
+        assert!(sizes.len() > 1);
+        assert_eq!(sizes.iter().map(|(_, size)| size).sum::<usize>(), population.population_size);
+        for (id, size) in sizes {
+            let specie = population.get_species(id).unwrap();
+            assert_eq!(specie.id, id);
+            assert_eq!(specie.genomes.len(), size);
+            assert_eq!(population.genome_species.iter().filter(|&&s| s == id).count(), size);
+        }
+        assert!(population.get_species(usize::MAX).is_none());
+    }
+
+    #[test]
+    fn resize() {
+        let fitness = |genome: &mut Genome, _: bool| genome.fitness = genome.genes.len() as f64;
+        let mut population = Population::new(20, 2, 1, 0).unwrap();
+        assert!(population.resize(0).is_err());
+
+        population.resize(50).unwrap();
+        population.evaluate(&fitness);
+        // The champion is carried over on top of the bred genomes
+        assert_eq!(population.len(), 51);
+        assert_eq!(population.genome_species.len(), 51);
+
+        population.resize(10).unwrap();
+        let evaluated = std::cell::RefCell::new(vec![]);
+        population.evaluate(&|genome, validation| {
+            fitness(genome, validation);
+            evaluated.borrow_mut().push(genome.fitness);
+        });
+        assert_eq!(evaluated.borrow().len(), 51);
+        assert_eq!(population.len(), 11);
+        assert_eq!(population.genome_species.len(), 11);
+        let best = evaluated.borrow().iter().copied().fold(f64::MIN, f64::max);
+        let champion = population.champion.clone().unwrap();
+        assert!(champion.fitness >= best);
+        assert!(population.genomes().any(|genome| genome.fitness == champion.fitness));
+
+        // Growing past the size by injecting a genome doesn't cull the next generation
+        population.inject_genome(champion).unwrap();
+        assert_eq!(population.len(), 12);
+        assert_eq!(population.pending_size, None);
+    }
+
+    #[test]
+    fn compact_innovation_record() {
+        let mut population = Population::new(30, 2, 1, 0).unwrap();
+        for _ in 0..10 {
+            population.evaluate(&|genome, _| genome.fitness = 1.0);
+        }
+        let before = population.innovation_record().innovation_number.len();
+        population.genomes.iter_mut().for_each(|genome| genome.genes.truncate(3));
+        population.champion = None;
+
+        population.compact_innovation_record();
+        let record = population.innovation_record();
+        assert!(record.innovation_number.len() < before);
+        for gene in &population.genomes[0].genes {
+            assert_eq!(record.innovation_number[&(gene.in_node, gene.out_node)], gene.innovation);
+        }
+    }
+
+    // Single step episodes rewarding the network's output for a constant observation
+    struct Bandit {
+        steps: usize,
+    }
+
+    impl Environment for Bandit {
+        fn reset(&mut self) -> Vec<f64> {
+            vec![1.0, 0.5]
+        }
+
+        fn step(&mut self, action: &[f64]) -> (Vec<f64>, f64, bool) {
+            self.steps += 1;
+            (vec![1.0, 0.5], action[0], true)
+        }
+
+        fn input_size(&self) -> usize {
+            2
+        }
+    }
+
+    #[test]
+    fn multi_parent_crossover() {
+        let config = NeatConfig {
+            crossover_parents: 3,
+            ..NeatConfig::default()
+        };
+        let mut population = Population::with_config(30, 2, 1, 0, config).unwrap();
+        for _ in 0..10 {
+            population.evaluate(&xor_fitness);
+        }
+        assert!(population.genomes().all(|genome| genome.validate().is_ok()));
+    }
+
+    #[test]
+    fn wiring_cost_pressure() {
+        let config = NeatConfig {
+            wiring_cost_coefficient: 0.1,
+            ..NeatConfig::default()
+        };
+        let mut population = Population::with_config(20, 2, 1, 0, config).unwrap();
+        // The same three connections, with the second input wired to the hidden node or past it
+        let innovation_record = &mut population.innovation_record;
+        let mut spec = |second: (usize, usize, f64, bool)| {
+            let mut genome =
+                Genome::from_spec(2, 1, 1, &[(0, 4, 1.0, true), (4, 3, 1.0, true), second], innovation_record);
+            genome.fitness = 1.0;
+            genome
+        };
+        let long = spec((1, 3, 1.0, true));
+        let local = spec((1, 4, 1.0, true));
+        assert_eq!((long.wiring_cost(), local.wiring_cost()), (4.0, 3.0));
+        assert!((local.selection_fitness(&population.config) - 0.7).abs() < 1e-12);
+
+        // The locally wired genome survives culling and its species gets more offspring
+        let mut specie = Specie::new(0, long.clone());
+        specie.add_genome(local.clone());
+        specie.cull(&population.config);
+        assert_eq!(specie.genomes[0].wiring_cost(), 3.0);
+
+        population.genomes = [long, local].iter().flat_map(|genome| vec![genome.clone(); 10]).collect();
+        population.genome_species = (0..2).flat_map(|id| [id; 10]).collect();
+        population.species = vec![
+            Specie::new(0, population.genomes[0].clone()),
+            Specie::new(1, population.genomes[10].clone()),
+        ];
+        population.refresh_species();
+        let counts = population.offspring_counts();
+        assert!(counts[1] > counts[0]);
+
+        // Reported fitness stays the raw score
+        population.evolve();
+        assert_eq!(population.champion.unwrap().fitness, 1.0);
+    }
+
+    #[test]
+    fn mutation_schedule() {
+        let config = NeatConfig {
+            mutation_schedule: MutationSchedule::Linear { generations: 10, end_scale: 0.1 },
+            ..NeatConfig::default()
+        };
+        let mut population = Population::with_config(20, 2, 1, 0, config).unwrap();
+        assert_eq!(population.effective_mutation_probs(), (0.2, 0.5));
+        for _ in 0..10 {
+            population.evaluate(&xor_fitness);
+        }
+        let (new_node_prob, new_connection_prob) = population.effective_mutation_probs();
+        assert!((new_node_prob - 0.02).abs() < 1e-12);
+        assert!((new_connection_prob - 0.05).abs() < 1e-12);
+
+        let schedule = MutationSchedule::Exponential { decay: 0.5, min_scale: 0.2 };
+        assert_eq!(schedule.scale(1), 0.5);
+        assert_eq!(schedule.scale(10), 0.2);
+    }
+
+    #[test]
+    fn merge() {
+        let mut population = Population::new(10, 2, 1, 0).unwrap();
+        let mut other = Population::new(10, 2, 1, 0).unwrap();
+        for _ in 0..5 {
+            population.evaluate(&xor_fitness);
+            other.evaluate(&xor_fitness);
+        }
+        let inputs = [vec![0.0, 1.0], vec![1.0, 1.0]];
+        let outputs = |genomes: &[Genome]| -> Vec<Vec<f64>> {
+            genomes
+                .iter()
+                .flat_map(|genome| inputs.iter().map(|input| genome.compile(&NeatConfig::default())(input)))
+                .collect()
+        };
+        let merged_outputs = [outputs(&population.genomes), outputs(&other.genomes)].concat();
+        let sizes = (population.len(), other.len());
+
+        population.merge(other).unwrap();
+        assert_eq!(population.len(), sizes.0 + sizes.1);
+        assert_eq!(population.population_size, 20);
+        assert_eq!(outputs(&population.genomes), merged_outputs);
+        assert!(population.genomes().all(|genome| genome.validate().is_ok()));
+        assert_eq!(population.genome_species.len(), population.len());
+        let species_total: usize = population.species.iter().map(|specie| specie.genomes.len()).sum();
+        assert_eq!(species_total, population.len());
+
+        let wrong_size = Population::new(10, 3, 1, 0).unwrap();
+        assert_eq!(
+            population.merge(wrong_size).unwrap_err(),
+            PopulationError::IncompatiblePopulation { expected: (2, 1), found: (3, 1) }
+        );
+        let mut different = Population::new(10, 2, 1, 0).unwrap();
+        different.config.compatibility_threshold += 1.0;
+        assert_eq!(population.merge(different).unwrap_err(), PopulationError::ConfigMismatch);
+        population.evaluate(&xor_fitness);
+    }
+
+    #[test]
+    fn merge_matches_splits() {
+        let mut population = Population::new(10, 2, 1, 0).unwrap();
+        // Seeds may differ
+        let config = NeatConfig {
+            seed: Some(1),
+            ..NeatConfig::default()
+        };
+        let mut other = Population::with_config(10, 2, 1, 0, config).unwrap();
+        // Records of runs that only have the initial connections so far
+        let mut innovation_record = InnovationRecord::new();
+        Genome::new(2, 1, &mut innovation_record);
+        population.innovation_record = innovation_record.clone();
+        other.innovation_record = innovation_record;
+        // Both runs split 0 -> 3, each into a new node of their own
+        other.innovation_record.new_node_innovation();
+        let connections = [(0, 3, 1.0, false), (0, 4, 1.0, true), (4, 3, 0.5, true), (1, 3, 1.0, true)];
+        let from_spec = |connections: &[(usize, usize, f64, bool)], record: &mut InnovationRecord| {
+            let mut genome = Genome::from_spec(2, 1, 1, connections, &mut InnovationRecord::new());
+            let id = record.new_node_innovation();
+            genome.renumber_nodes(&HashMap::from([(4, id)]), record);
+            genome
+        };
+        let split = from_spec(&connections, &mut population.innovation_record);
+        let other_split = from_spec(&connections, &mut other.innovation_record);
+        // A split only the other run has
+        let connections = [(1, 3, 1.0, false), (1, 4, 1.0, true), (4, 3, 1.0, true)];
+        let unmatched = from_spec(&connections, &mut other.innovation_record);
+        population.genomes = vec![split.clone()];
+        other.genomes = vec![other_split, unmatched];
+
+        population.merge(other).unwrap();
+        let genes = |genome: &Genome| -> Vec<(usize, usize, usize)> {
+            let mut genes: Vec<_> =
+                genome.genes.iter().map(|gene| (gene.in_node, gene.out_node, gene.innovation)).collect();
+            genes.sort();
+            genes
+        };
+        assert_eq!(genes(&population.genomes[1]), genes(&split));
+        assert_eq!(population.genomes[1].compatability_distance(&split, &population.config), 0.0);
+        let fresh = population.genomes[2].node.last().unwrap().id;
+        assert_eq!(fresh, population.innovation_record.num_nodes - 1);
+        assert!(population.genomes().all(|genome| genome.validate().is_ok()));
+    }
+
+    #[test]
+    fn assign_species() {
+        let mut population = Population::new(30, 2, 1, 0).unwrap();
+        population.evaluate(&xor_fitness);
+        let representative = population.species[0].representative.clone();
+        assert_eq!(population.assign_species(&representative), Some(population.species[0].id));
+
+        let species = population.species.len();
+        population.config.compatibility_threshold = 1e-9;
+        let mut candidate = representative.clone();
+        candidate.genes[0].weight += 1.0;
+        assert_eq!(population.assign_species(&candidate), None);
+        assert_eq!(population.species.len(), species);
+    }
+
+    #[test]
+    fn set_compatibility_threshold() {
+        let mut population = Population::new(30, 2, 1, 0).unwrap();
+        assert!(population.set_compatibility_threshold(0.0).is_err());
+        population.set_compatibility_threshold(0.3).unwrap();
+        for _ in 0..10 {
+            population.evaluate(&xor_fitness);
+            assert_eq!(population.config.compatibility_threshold, 0.3);
+        }
+    }
+
+    #[test]
+    fn run_for() {
+        let mut population = Population::new(20, 2, 1, 0).unwrap();
+        let start = Instant::now();
+        let mut ages = vec![];
+        let generations = population.run_for(Duration::from_millis(1), &xor_fitness, &mut |population| {
+            ages.push(population.age)
+        });
+        assert!(generations >= 1);
+        assert_eq!(population.age, generations);
+        assert_eq!(ages, (1..=generations).collect::<Vec<_>>());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn fitness_cache() {
+        let config = NeatConfig {
+            cache_fitness: true,
+            global_elitism: 5,
+            ..NeatConfig::default()
+        };
+        let mut population = Population::with_config(30, 2, 1, 0, config).unwrap();
+        let evaluations = std::cell::RefCell::new(HashMap::new());
+        let fitness = |genome: &mut Genome, display: bool| {
+            *evaluations.borrow_mut().entry(genome.structural_hash()).or_insert(0) += 1;
+            xor_fitness(genome, display);
+        };
+        for generation in 0..10 {
+            evaluations.borrow_mut().clear();
+            let len = population.len();
+            population.evaluate(&fitness);
+            // Genomes are evaluated once per generation, and elites carried over unchanged from
+            // the previous one not at all
+            assert!(evaluations.borrow().values().all(|&count| count == 1));
+            if generation > 0 {
+                assert!(evaluations.borrow().len() <= len - 5);
+            }
+        }
+    }
+
+    #[test]
+    fn evaluate_multi() {
+        let mut population = Population::new(20, 2, 1, 0).unwrap();
+        population.evaluate_multi(3, &|_, case| [1.0, 2.0, 6.0][case]);
+        assert_eq!(population.champion.as_ref().unwrap().fitness, 3.0);
+    }
+
+    #[test]
+    fn evaluate_episodes() {
+        let mut population = Population::new(20, 2, 1, 0).unwrap();
+        let mut bandit = Bandit { steps: 0 };
+        population.evaluate_episodes(&mut bandit, 3);
+        assert_eq!(bandit.steps, 60);
+
+        let mut champion = population.champion.clone().unwrap();
+        let output = champion.feed_forward(vec![1.0, 0.5], &NeatConfig::default())[0];
+        assert!((champion.fitness - 3.0 * output).abs() < 1e-12);
+    }
+
+    #[test]
+    fn species_elitism() {
+        let counts = |species_elitism: bool| {
+            let config = NeatConfig {
+                species_elitism,
+                ..NeatConfig::default()
+            };
+            let mut population = Population::with_config(20, 2, 1, 0, config).unwrap();
+            let genome = population.genomes[0].clone();
+            population.species = (0..2)
+                .map(|id| {
+                    let mut member = genome.clone();
+                    member.fitness = if id == 0 { 5.0 } else { 1.0 };
+                    let mut specie = Specie::new(id, member.clone());
+                    specie.add_genome(member);
+                    specie
+                })
+                .collect();
+            // The fittest species has stopped improving
+            population.species[0].stagnation = 20;
+            population.offspring_counts()
+        };
+        assert!(counts(true)[0] > 0);
+        assert_eq!(counts(false)[0], 0);
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut population = Population::new(30, 2, 1, 0).unwrap();
+        population.config.compatibility_threshold = 0.5;
+        for _ in 0..5 {
+            population.evaluate(&|genome, _| genome.fitness = genome.genes.len() as f64);
+        }
+        let snapshot = population.snapshot();
+        assert_eq!(snapshot.species.len(), population.species.len());
+        assert_eq!(snapshot.generation, 5);
+
+        let mut restored = Population::from_snapshot(snapshot.clone(), 3);
+        assert_eq!(restored.genomes.len(), population.genomes.len());
+        assert_eq!(restored.species.len(), population.species.len());
+        for (specie_id, genomes) in &snapshot.species {
+            let specie = restored.species.iter().find(|specie| specie.id == *specie_id).unwrap();
+            assert_eq!(specie.genomes.len(), genomes.len());
+            let expected = population.genome_species.iter().filter(|id| *id == specie_id).count();
+            assert_eq!(restored.genome_species.iter().filter(|id| *id == specie_id).count(), expected);
+        }
+        restored.evaluate(&|genome, _| genome.fitness = 1.0);
+        assert_eq!(restored.age, 6);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::config::NeatConfig;
+use crate::environment::Environment;
+use crate::genome::Genome;
+use crate::population::{Population, PopulationError};
+
+// Fitness of one generation, taken before it is replaced by its offspring
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenerationStats {
+    pub generation: usize,
+    pub best_fitness: f64,
+    pub mean_fitness: f64,
+}
+
+pub struct ExperimentResult {
+    pub best_genome: Genome,
+    pub best_fitness: f64,
+    pub stats_history: Vec<GenerationStats>,
+    pub generations_run: usize,
+}
+
+type FitnessFunction<'a> = Box<dyn Fn(&mut Genome, bool) + 'a>;
+type GenerationCallback<'a> = Box<dyn FnMut(&Population, &GenerationStats) + 'a>;
+
+enum FitnessSource<'a> {
+    Function(FitnessFunction<'a>),
+    Environment(&'a mut dyn Environment, usize),
+}
+
+// Runs the usual evaluate and evolve loop for a number of generations, stopping early once the
+// config's fitness threshold is reached. Genomes are scored either by a fitness function, like
+// `Population::evaluate`, or by episodes of an environment, like `Population::evaluate_episodes`
+pub struct Experiment<'a> {
+    population_size: usize,
+    inputs: usize,
+    outputs: usize,
+    config: NeatConfig,
+    generations: usize,
+    fitness: Option<FitnessSource<'a>>,
+    callback: Option<GenerationCallback<'a>>,
+}
+
+impl<'a> Experiment<'a> {
+    pub fn new(population_size: usize, inputs: usize, outputs: usize) -> Self {
+        Self {
+            population_size,
+            inputs,
+            outputs,
+            config: NeatConfig::default(),
+            generations: 100,
+            fitness: None,
+            callback: None,
+        }
+    }
+
+    pub fn config(mut self, config: NeatConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn generations(mut self, generations: usize) -> Self {
+        self.generations = generations;
+        self
+    }
+
+    pub fn fitness(mut self, f: impl Fn(&mut Genome, bool) + 'a) -> Self {
+        self.fitness = Some(FitnessSource::Function(Box::new(f)));
+        self
+    }
+
+    pub fn environment(mut self, environment: &'a mut dyn Environment, episodes: usize) -> Self {
+        self.fitness = Some(FitnessSource::Environment(environment, episodes));
+        self
+    }
+
+    // Called after each generation is evaluated, before it is evolved
+    pub fn on_generation(mut self, callback: impl FnMut(&Population, &GenerationStats) + 'a) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    // Panics if neither a fitness function nor an environment was given
+    pub fn run(mut self) -> Result<ExperimentResult, PopulationError> {
+        let mut fitness = self.fitness.take().expect("experiment needs a fitness function or environment");
+        if self.generations == 0 {
+            return Err(PopulationError::InvalidConfig("experiment needs at least one generation".to_string()));
+        }
+        let mut population =
+            Population::with_config(self.population_size, self.inputs, self.outputs, 0, self.config)?;
+        let mut stats_history = vec![];
+
+        for generation in 0..self.generations {
+            match &mut fitness {
+                FitnessSource::Function(f) => population.assign_fitness(f.as_ref()),
+                FitnessSource::Environment(environment, episodes) => {
+                    population.assign_episode_fitness(*environment, *episodes)
+                }
+            }
+            let stats = GenerationStats {
+                generation,
+                best_fitness: population.genomes().map(|genome| genome.fitness).fold(f64::MIN, f64::max),
+                mean_fitness: population.genomes().map(|genome| genome.fitness).sum::<f64>()
+                    / population.len() as f64,
+            };
+            if let Some(callback) = &mut self.callback {
+                callback(&population, &stats);
+            }
+            stats_history.push(stats);
+
+            population.evolve();
+            if population.is_solved() {
+                break;
+            }
+        }
+
+        let best_genome = population.champion.expect("at least one generation was run");
+        Ok(ExperimentResult {
+            best_fitness: best_genome.fitness,
+            best_genome,
+            generations_run: stats_history.len(),
+            stats_history,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::mse;
+
+    #[test]
+    fn xor_experiment() {
+        let xor = vec![
+            (vec![0.0, 0.0], vec![0.0]),
+            (vec![0.0, 1.0], vec![1.0]),
+            (vec![1.0, 0.0], vec![1.0]),
+            (vec![1.0, 1.0], vec![0.0]),
+        ];
+        let config = NeatConfig {
+            fitness_threshold: Some(3.9),
+            seed: Some(3),
+            ..NeatConfig::default()
+        };
+        let mut generations_seen = 0;
+        let result = Experiment::new(150, 2, 1)
+            .config(config.clone())
+            .generations(300)
+            .fitness(|genome: &mut Genome, _| genome.fitness = 4.0 - 4.0 * mse(&genome.compile(&config), &xor))
+            .on_generation(|_, _| generations_seen += 1)
+            .run()
+            .unwrap();
+
+        assert!(result.best_fitness >= 3.9);
+        assert_eq!(result.generations_run, generations_seen);
+        assert_eq!(result.stats_history.len(), result.generations_run);
+        assert!(result.stats_history.iter().all(|stats| stats.mean_fitness <= stats.best_fitness));
+    }
+
+    #[test]
+    fn no_generations() {
+        let result = Experiment::new(10, 2, 1)
+            .generations(0)
+            .fitness(|genome: &mut Genome, _| genome.fitness = 1.0)
+            .run();
+        assert!(matches!(result, Err(PopulationError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn cached_fitness() {
+        let config = NeatConfig {
+            cache_fitness: true,
+            seed: Some(1),
+            ..NeatConfig::default()
+        };
+        let evaluations = std::cell::Cell::new(0);
+        Experiment::new(30, 2, 1)
+            .config(config)
+            .generations(5)
+            .fitness(|genome: &mut Genome, _| {
+                evaluations.set(evaluations.get() + 1);
+                genome.fitness = genome.genes.len() as f64;
+            })
+            .run()
+            .unwrap();
+        // Structurally identical genomes, like the carried over champion, are scored only once
+        assert!(evaluations.get() < 30 + 4 * 31);
+    }
+}
@@ -1,7 +1,8 @@
+use crate::config::NeatConfig;
 use crate::genome::Genome;
 use crate::innovation_record::InnovationRecord;
 use rand::seq::SliceRandom;
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 pub struct Specie {
     pub id: usize,
@@ -10,6 +11,9 @@ pub struct Specie {
     pub representative: Genome,
     pub average_fitness: f64,
     pub stagnation: usize,
+    pub age: usize,
+    // Species closest to the genome that founded this one, if there was any
+    pub parent_id: Option<usize>,
 }
 
 impl Specie {
@@ -23,26 +27,28 @@ impl Specie {
             representative,
             average_fitness,
             stagnation: 0,
+            age: 0,
+            parent_id: None,
         }
     }
 
     // Does genome fit in species
-    pub fn match_genome(&mut self, genome: &Genome) -> bool {
-        self.representative.compatability_distance(genome) < 2.0
+    pub fn match_genome(&self, genome: &Genome, config: &NeatConfig) -> bool {
+        self.representative.speciation_distance(genome, config) < config.compatibility_threshold
     }
 
     pub fn add_genome(&mut self, genome: Genome) {
         self.genomes.push(genome);
     }
 
-    // Calculates average fitness of species
+    // Calculates average fitness of species from the members' selection fitness
     // Returns sum of adj fitness
-    pub fn calculate_average_fitness(&mut self) -> f64 {
+    pub fn calculate_average_fitness(&mut self, config: &NeatConfig) -> f64 {
         let genome_count = self.genomes.len() as f64;
 
         // Fitness sharing
         self.genomes.iter_mut().for_each(|genome| {
-            genome.adj_fitness = genome.fitness / genome_count;
+            genome.adj_fitness = genome.selection_fitness(config) / genome_count;
         });
 
         let total = self.genomes.iter().fold(0.0, |acc, genome| acc + genome.adj_fitness);
@@ -60,38 +66,168 @@ impl Specie {
         total
     }
 
-    pub fn select_genome(&self) -> Genome {
-        let mut rng = rand::thread_rng();
-        self.genomes.choose(&mut rng).unwrap().clone()
+    // Multiplier applied to the species' fitness when allocating offspring
+    pub fn offspring_bonus(&self, config: &NeatConfig) -> f64 {
+        if self.age < config.young_age_threshold {
+            config.young_species_bonus
+        } else {
+            1.0
+        }
+    }
+
+    // Number of offspring the species produces given the population's total adjusted fitness
+    pub fn offspring_count(&self, total_adjusted_fitness: f64, config: &NeatConfig) -> usize {
+        let fitness = self.average_fitness * self.offspring_bonus(config);
+        let offspring_num = ((fitness / total_adjusted_fitness) * self.genomes.len() as f64) as usize;
+        offspring_num.max(1)
+    }
+
+    pub fn select_genome(&self, rng: &mut dyn RngCore) -> Genome {
+        self.genomes.choose(rng).unwrap().clone()
+    }
+
+    pub fn best_fitness(&self) -> f64 {
+        self.genomes
+            .iter()
+            .fold(f64::MIN, |acc, genome| acc.max(genome.fitness))
+    }
+
+    // Member with the highest fitness, the earliest one on ties
+    pub fn fittest_genome(&self) -> Genome {
+        self.genomes
+            .iter()
+            .reduce(|best, genome| if genome.fitness > best.fitness { genome } else { best })
+            .unwrap()
+            .clone()
     }
 
-    pub fn make_child(&self, innovation_record: &mut InnovationRecord) -> Genome {
-        let mut rng = rand::thread_rng();
+    pub fn make_child(
+        &self,
+        innovation_record: &mut InnovationRecord,
+        config: &NeatConfig,
+        rng: &mut dyn RngCore,
+    ) -> Genome {
         let mut child = if rng.gen::<f64>() < 0.25 {
-            let mut parent = self.select_genome();
-            parent.mutate(innovation_record);
+            let mut parent = self.select_genome(rng);
+            parent.mutate(innovation_record, config, rng);
             parent
+        } else if config.crossover_parents > 2 {
+            let parents: Vec<Genome> = (0..config.crossover_parents).map(|_| self.select_genome(rng)).collect();
+            Genome::multi_crossover(&parents.iter().collect::<Vec<_>>(), config, rng)
         } else {
-            let mut parent_1 = self.select_genome();
-            let mut parent_2 = self.select_genome();
-
-            if parent_1 < parent_2 {
-                parent_1.crossover(parent_2)
-            } else {
-                parent_2.crossover(parent_1)
-            }
+            let (mut fitter, other) = order_parents(self.select_genome(rng), self.select_genome(rng), config);
+            fitter.crossover(other, config, rng)
         };
-        child.mutate(innovation_record);
+        child.mutate(innovation_record, config, rng);
         child
     }
 
-    pub fn cull(&mut self) -> usize {
+    pub fn cull(&mut self, config: &NeatConfig) -> usize {
         let prev_len = self.genomes.len();
-        self.genomes.sort();
-        // Remove second half (lowest fitness)
-        if prev_len > 3 {
-            self.genomes.truncate(self.genomes.len() / 2);
-        }
+        self.genomes
+            .sort_by(|a, b| b.selection_fitness(config).total_cmp(&a.selection_fitness(config)));
+        // Keep the fittest survivors, always at least one
+        let survivors = (prev_len as f64 * config.survival_threshold).ceil() as usize;
+        self.genomes.truncate(survivors.max(1));
         prev_len
     }
 }
+
+// Fitter parent first, on a tie the second one unless the smaller genome is preferred
+fn order_parents(parent_1: Genome, parent_2: Genome, config: &NeatConfig) -> (Genome, Genome) {
+    let first_fitter = if parent_1.fitness == parent_2.fitness && config.crossover_equal_fitness_prefer_smaller {
+        parent_1.genes.len() <= parent_2.genes.len()
+    } else {
+        parent_1 < parent_2
+    };
+    if first_fitter {
+        (parent_1, parent_2)
+    } else {
+        (parent_2, parent_1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cull_survival_threshold() {
+        let mut innovation_record = InnovationRecord::new();
+        let genome = Genome::new(2, 1, &mut innovation_record);
+        let mut specie = Specie::new(0, genome.clone());
+        for i in 1..10 {
+            let mut member = genome.clone();
+            member.fitness = i as f64;
+            specie.add_genome(member);
+        }
+
+        let config = NeatConfig {
+            survival_threshold: 0.2,
+            ..NeatConfig::default()
+        };
+        assert_eq!(specie.cull(&config), 10);
+        assert_eq!(specie.genomes.len(), 2);
+        assert_eq!(specie.genomes[0].fitness, 9.0);
+        assert_eq!(specie.genomes[1].fitness, 8.0);
+
+        // Always at least one survivor
+        let config = NeatConfig {
+            survival_threshold: 0.0,
+            ..NeatConfig::default()
+        };
+        specie.cull(&config);
+        assert_eq!(specie.genomes.len(), 1);
+    }
+
+    #[test]
+    fn young_species_bonus() {
+        let mut innovation_record = InnovationRecord::new();
+        let mut genome = Genome::new(2, 1, &mut innovation_record);
+        genome.fitness = 1.0;
+        let config = NeatConfig {
+            young_species_bonus: 2.0,
+            young_age_threshold: 5,
+            ..NeatConfig::default()
+        };
+
+        // Two species with identical fitness, one of them just created
+        let mut old_specie = Specie::new(0, genome.clone());
+        let mut young_specie = Specie::new(1, genome.clone());
+        for _ in 0..9 {
+            old_specie.add_genome(genome.clone());
+            young_specie.add_genome(genome.clone());
+        }
+        old_specie.age = 20;
+
+        let old_fitness = old_specie.calculate_average_fitness(&config) * old_specie.offspring_bonus(&config);
+        let young_fitness = young_specie.calculate_average_fitness(&config) * young_specie.offspring_bonus(&config);
+        let total_adjusted_fitness = (old_fitness + young_fitness) / 20.0;
+        let old_offspring = old_specie.offspring_count(total_adjusted_fitness, &config);
+        let young_offspring = young_specie.offspring_count(total_adjusted_fitness, &config);
+        assert!(young_offspring > old_offspring);
+    }
+
+    #[test]
+    fn equal_fitness_prefers_smaller() {
+        let mut innovation_record = InnovationRecord::new();
+        let config = NeatConfig {
+            crossover_equal_fitness_prefer_smaller: true,
+            ..NeatConfig::default()
+        };
+        let small = Genome::new(2, 1, &mut innovation_record);
+        let mut large = small.clone();
+        large.add_node(&mut innovation_record, &config, &mut rand::thread_rng());
+
+        for (parent_1, parent_2) in [(small.clone(), large.clone()), (large.clone(), small.clone())] {
+            let (mut fitter, other) = order_parents(parent_1, parent_2, &config);
+            assert_eq!(fitter.genes.len(), small.genes.len());
+            let child = fitter.crossover(other, &config, &mut rand::thread_rng());
+            assert_eq!(child.genes.len(), small.genes.len());
+        }
+
+        // Without the option ties go to the second parent
+        let (fitter, _) = order_parents(small.clone(), large.clone(), &NeatConfig::default());
+        assert_eq!(fitter.genes.len(), large.genes.len());
+    }
+}